@@ -0,0 +1,17 @@
+-- Records who signed each transaction and with which nonce, so that the transactions could be
+-- reconciled with the chain after a restart.
+
+-- The index of the derived account that signed the transaction. NULL means the root account.
+ALTER TABLE txns ADD COLUMN sender_index INTEGER;
+-- The nonce the transaction was signed with.
+ALTER TABLE txns ADD COLUMN nonce INTEGER;
+-- One of:
+--
+-- `pending` the transaction was persisted but it's not known whether it made it on chain.
+-- `confirmed` the transaction (or at least its nonce) was included on chain.
+-- `obsolete` the transaction can no longer be included and was given up on.
+ALTER TABLE txns ADD COLUMN status TEXT NOT NULL DEFAULT 'pending';
+
+-- The transactions persisted before this migration carry no sender information and thus cannot be
+-- reconciled.
+UPDATE txns SET status = 'obsolete';
@@ -0,0 +1,27 @@
+-- Tracks the lifecycle of every transaction.
+
+-- The hash of the transaction, i.e. the blake2-256 hash of `extrinsic_data`.
+ALTER TABLE txns ADD COLUMN hash BLOB;
+-- Either `transfer_all` or `mint`.
+ALTER TABLE txns ADD COLUMN kind TEXT;
+-- The hash of the block the transaction was included in.
+ALTER TABLE txns ADD COLUMN block_hash BLOB;
+-- The index of the transaction within the block it was included in.
+ALTER TABLE txns ADD COLUMN extrinsic_index INTEGER;
+
+CREATE INDEX txns_hash ON txns (hash);
+
+-- The statuses are now the following:
+--
+-- `signed` the transaction was persisted but not yet seen by the node.
+-- `submitted` the transaction is in the pool.
+-- `in-best-block` the transaction is included in the best block.
+-- `finalized` the transaction is included in a finalized block.
+-- `dropped` the transaction was dropped from the pool.
+-- `invalid` the transaction was deemed invalid by the node.
+--
+-- The reconciliation on startup may also leave the following:
+--
+-- `confirmed` the nonce of the transaction was used on chain, but it is unknown where.
+-- `obsolete` the transaction can no longer be included and was given up on.
+UPDATE txns SET status = 'signed' WHERE status = 'pending';
@@ -0,0 +1,3 @@
+-- Inclusion in a block doesn't mean the transaction succeeded. The transactions that were included
+-- but failed to dispatch get the `failed` status along with the reason stored here.
+ALTER TABLE txns ADD COLUMN error TEXT;
@@ -0,0 +1,17 @@
+-- `derivation` is the derivation path template of the accounts, e.g. `//{index}`. It gets fixed on
+-- the first run, since the pending transactions might have to be re-signed by the same
+-- accounts. The databases that already derived accounts used the hardcoded `//{index}`.
+INSERT INTO kv (key, value) VALUES ('derivation', NULL);
+UPDATE kv SET value = '//{index}'
+WHERE key = 'derivation' AND (SELECT value FROM kv WHERE key = 'next_index') != '0';
+
+-- The derived accounts, so they could be imported into a wallet and audited.
+CREATE TABLE accounts (
+    account_index INTEGER PRIMARY KEY,
+    -- The SS58 address for the chain the account was derived on.
+    address TEXT NOT NULL,
+    -- The raw public key. For ecdsa, the account ID is its hash.
+    public_key BLOB NOT NULL,
+    -- The derivation path relative to the root account, e.g. `//5`.
+    derivation_path TEXT NOT NULL
+);
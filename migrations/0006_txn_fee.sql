@@ -0,0 +1,3 @@
+-- The fee paid for the transaction in the smallest units, as reported by the
+-- `TransactionPayment.TransactionFeePaid` event. Recorded once the transaction is finalized.
+ALTER TABLE txns ADD COLUMN fee INTEGER;
@@ -0,0 +1,4 @@
+-- The number and the timestamp (in milliseconds since the epoch) of the finalized block the
+-- transaction was included in. Used to break the costs down over time.
+ALTER TABLE txns ADD COLUMN block_number INTEGER;
+ALTER TABLE txns ADD COLUMN block_time INTEGER;
@@ -0,0 +1,27 @@
+-- The ksm-20 state computed by the `index` command from the remarks on chain.
+--
+-- `ksm20_from_block` is the block the indexing started at and `ksm20_last_block` the last block
+-- that was indexed, so that the indexing could be resumed.
+INSERT INTO kv (key, value) VALUES ('ksm20_from_block', NULL);
+INSERT INTO kv (key, value) VALUES ('ksm20_last_block', NULL);
+
+-- The deployed ticks. The amounts are decimal strings, since they might not fit into an INTEGER.
+CREATE TABLE ksm20_ticks (
+    -- Lowercase, the ticks are case-insensitive.
+    tick TEXT PRIMARY KEY,
+    max TEXT NOT NULL,
+    lim TEXT NOT NULL,
+    minted TEXT NOT NULL,
+    -- The SS58 address of the account that deployed the tick.
+    deployer TEXT NOT NULL,
+    -- The block the tick was deployed in.
+    block_number INTEGER NOT NULL
+);
+
+-- The balances of every holder, keyed by the SS58 address of the chain.
+CREATE TABLE ksm20_balances (
+    tick TEXT NOT NULL,
+    address TEXT NOT NULL,
+    balance TEXT NOT NULL,
+    PRIMARY KEY (tick, address)
+);
@@ -0,0 +1,4 @@
+-- `genesis_hash` is the hex-encoded genesis hash of the chain the database is used with. It gets
+-- fixed on the first run, since the transactions, the blocks and the addresses the database keeps
+-- only make sense on that chain.
+INSERT INTO kv (key, value) VALUES ('genesis_hash', NULL);
@@ -0,0 +1,3 @@
+-- The account ID the transfers move the funds to, so that a transfer that reaped its sender could
+-- be recognized as landed. NULL for the mints and for the transfers recorded before.
+ALTER TABLE txns ADD COLUMN dest BLOB;
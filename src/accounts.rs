@@ -0,0 +1,163 @@
+//! Deriving the accounts of the chain from the root account.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use subxt_signer::{DeriveJunction, SecretUri};
+
+use crate::{db::AccountRecord, key::Keypair, metadata::Chain};
+
+/// The derivation path template of the accounts, relative to the root account.
+///
+/// The template is a substrate derivation path, e.g. `//inscribe//{index}` or `/{index}`, where
+/// `{index}` is replaced with the index of the account. Thus, an account could be imported into a
+/// wallet as the secret URI of the root account followed by the rendered path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Derivation {
+    template: String,
+}
+
+impl Derivation {
+    /// Hard-derives the index directly from the root. This is what was used before the template
+    /// became configurable.
+    pub const DEFAULT: &'static str = "//{index}";
+
+    pub fn parse(template: &str) -> Result<Self> {
+        if template.matches("{index}").count() != 1 {
+            bail!(
+                "The derivation template {:?} must contain {{index}} exactly once",
+                template
+            );
+        }
+        let derivation = Self {
+            template: template.to_string(),
+        };
+        derivation.junctions(0)?;
+        Ok(derivation)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Returns the path of the account at the given index.
+    pub fn path(&self, index: u32) -> String {
+        self.template.replace("{index}", &index.to_string())
+    }
+
+    /// Parses the path the same way the secret URIs are parsed, so numeric junctions are treated
+    /// as integers and everything else as strings.
+    fn junctions(&self, index: u32) -> Result<Vec<DeriveJunction>> {
+        let path = self.path(index);
+        if !path.starts_with('/') {
+            bail!("The derivation path {:?} must start with / or //", path);
+        }
+        let uri = SecretUri::from_str(&path)
+            .map_err(|e| anyhow!("Invalid derivation path {:?}: {}", path, e))?;
+        if uri.password.is_some() {
+            bail!("The derivation path {:?} must not contain a password", path);
+        }
+        Ok(uri.junctions)
+    }
+}
+
+/// The root account along with the accounts derived from it.
+pub struct Accounts {
+    root: Keypair,
+    derivation: Derivation,
+}
+
+impl Accounts {
+    /// Fails if the derivation is not supported by the scheme of the root account, e.g. soft
+    /// junctions for ed25519.
+    pub fn new(root: Keypair, derivation: Derivation) -> Result<Self> {
+        root.derive(derivation.junctions(0)?)?;
+        Ok(Self { root, derivation })
+    }
+
+    pub fn root(&self) -> &Keypair {
+        &self.root
+    }
+
+    pub fn derivation(&self) -> &Derivation {
+        &self.derivation
+    }
+
+    /// Derives the account at the given index.
+    ///
+    /// Calling this function with the same parameters must return the same result.
+    pub fn derive(&self, index: u32) -> Keypair {
+        let junctions = self
+            .derivation
+            .junctions(index)
+            .expect("the template was checked to render valid paths");
+        self.root
+            .derive(junctions)
+            .expect("the template was checked to be supported by the scheme")
+    }
+
+    /// Returns the account at the given index, or the root account for `None`.
+    pub fn get(&self, index: Option<u32>) -> Keypair {
+        match index {
+            None => Keypair::clone(&self.root),
+            Some(index) => self.derive(index),
+        }
+    }
+
+    /// Returns the record of the account at the given index for the `accounts` table.
+    pub fn record(&self, chain: Chain, index: u32) -> AccountRecord {
+        let keypair = self.derive(index);
+        AccountRecord {
+            index,
+            address: chain.to_ss58(&keypair.account_id()),
+            public_key: keypair.public_key(),
+            derivation_path: self.derivation.path(index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::{self, Scheme};
+
+    #[test]
+    fn templates() {
+        for template in [
+            "//inscribe",
+            "//{index}//{index}",
+            "{index}",
+            "inscribe//{index}",
+        ] {
+            assert!(Derivation::parse(template).is_err(), "{:?}", template);
+        }
+        assert!(Derivation::parse("//{index}///password").is_err());
+        assert!(Derivation::parse(Derivation::DEFAULT).is_ok());
+    }
+
+    #[test]
+    fn junctions() {
+        let derivation = Derivation::parse("//inscribe//{index}").unwrap();
+        assert_eq!(derivation.path(5), "//inscribe//5");
+        assert_eq!(
+            derivation.junctions(5).unwrap(),
+            [DeriveJunction::hard("inscribe"), DeriveJunction::hard(5u64)]
+        );
+        let derivation = Derivation::parse("//inscribe/{index}").unwrap();
+        assert_eq!(
+            derivation.junctions(5).unwrap(),
+            [DeriveJunction::hard("inscribe"), DeriveJunction::soft(5u64)]
+        );
+    }
+
+    #[test]
+    fn soft_junctions_need_sr25519() {
+        let soft = Derivation::parse("/{index}").unwrap();
+        let hard = Derivation::parse(Derivation::DEFAULT).unwrap();
+        let sr25519 = key::from_secret("//Alice", Scheme::Sr25519).unwrap();
+        let ed25519 = key::from_secret("//Alice", Scheme::Ed25519).unwrap();
+        assert!(Accounts::new(sr25519, soft.clone()).is_ok());
+        assert!(Accounts::new(ed25519.clone(), soft).is_err());
+        assert!(Accounts::new(ed25519, hard).is_ok());
+    }
+}
@@ -0,0 +1,683 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Result};
+use subxt::{config::substrate::H256, utils::AccountId32};
+
+use crate::Txn;
+
+#[derive(Clone)]
+pub struct Database {
+    sqlite: sqlx::SqlitePool,
+}
+
+impl Database {
+    pub async fn new() -> Result<Self> {
+        Self::open("sqlite://inscribed.sqlite").await
+    }
+
+    /// Opens the database at the given URL, e.g. `sqlite::memory:` for the tests.
+    pub async fn open(url: &str) -> Result<Self> {
+        let sqlite = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(url)
+            .await?;
+        sqlx::migrate!().run(&sqlite).await?;
+        Ok(Self { sqlite })
+    }
+
+    /// Returns the next usable index for deriving an account.
+    pub async fn get_next_index(&self) -> Result<u32> {
+        let row = sqlx::query!("SELECT value FROM kv WHERE key = 'next_index'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        let value = row
+            .value
+            .expect("next_index should be present")
+            .parse::<u32>()?;
+        Ok(value)
+    }
+
+    /// Returns the number of the last finalized block that was processed, if any.
+    pub async fn get_last_block(&self) -> Result<Option<u32>> {
+        let row = sqlx::query!("SELECT value FROM kv WHERE key = 'last_block'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        let value = row.value.map(|value| value.parse::<u32>()).transpose()?;
+        Ok(value)
+    }
+
+    /// Returns the derivation path template of the accounts, if it was fixed already.
+    pub async fn get_derivation(&self) -> Result<Option<String>> {
+        let row = sqlx::query!("SELECT value FROM kv WHERE key = 'derivation'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        Ok(row.value)
+    }
+
+    pub async fn set_derivation(&self, template: &str) -> Result<()> {
+        sqlx::query!("UPDATE kv SET value = ? WHERE key = 'derivation'", template)
+            .execute(&self.sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Fixes the database to the chain with the given genesis hash on the first run and refuses
+    /// to be used with any other chain afterwards.
+    pub async fn check_genesis_hash(&self, genesis_hash: H256) -> Result<()> {
+        let row = sqlx::query!("SELECT value FROM kv WHERE key = 'genesis_hash'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        let genesis_hash = hex::encode(genesis_hash);
+        match row.value {
+            Some(stored) if stored != genesis_hash => {
+                bail!(
+                    "The database is used with the chain with the genesis hash 0x{}",
+                    stored
+                )
+            }
+            Some(_) => {}
+            None => {
+                sqlx::query!(
+                    "UPDATE kv SET value = ? WHERE key = 'genesis_hash'",
+                    genesis_hash
+                )
+                .execute(&self.sqlite)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of the recorded accounts. The accounts are recorded in the order of
+    /// their indices, so that's also the index of the first unrecorded one.
+    pub async fn get_account_count(&self) -> Result<u32> {
+        let row = sqlx::query!("SELECT COUNT(*) AS count FROM accounts")
+            .fetch_one(&self.sqlite)
+            .await?;
+        Ok(row.count as u32)
+    }
+
+    /// Records the given accounts, ignoring the ones that are already recorded.
+    pub async fn record_accounts(&self, accounts: Vec<AccountRecord>) -> Result<()> {
+        let mut tx = self.sqlite.begin().await?;
+        for account in accounts {
+            account.insert(&mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Bumps the next index and persists the given transactions as signed along with the accounts
+    /// that sign them.
+    pub async fn update(
+        &self,
+        new_next_index: u32,
+        txns: Vec<Txn>,
+        accounts: Vec<AccountRecord>,
+    ) -> Result<()> {
+        let mut tx = self.sqlite.begin().await?;
+        sqlx::query!(
+            "UPDATE kv SET value = ? WHERE key = 'next_index'",
+            new_next_index
+        )
+        .execute(&mut *tx)
+        .await?;
+        for account in accounts {
+            account.insert(&mut tx).await?;
+        }
+        for txn in txns {
+            let hash = txn.hash();
+            let hash = hash.as_bytes();
+            let kind = txn.kind.as_str();
+            let nonce = txn.nonce as i64;
+            let status = TxnStatus::Signed.as_str();
+            sqlx::query!(
+                "INSERT INTO txns (extrinsic_data, hash, kind, sender_index, nonce, status)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                txn.data,
+                hash,
+                kind,
+                txn.sender,
+                nonce,
+                status
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Persists the given transaction as signed without touching the next index.
+    pub async fn insert(&self, txn: Txn) -> Result<()> {
+        let hash = txn.hash();
+        let hash = hash.as_bytes();
+        let kind = txn.kind.as_str();
+        let nonce = txn.nonce as i64;
+        let status = TxnStatus::Signed.as_str();
+        let dest = txn.dest.as_ref().map(|dest| dest.0.as_slice());
+        sqlx::query!(
+            "INSERT INTO txns (extrinsic_data, hash, kind, sender_index, nonce, status, dest)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            txn.data,
+            hash,
+            kind,
+            txn.sender,
+            nonce,
+            status,
+            dest
+        )
+        .execute(&self.sqlite)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the transactions that never made it into a block as far as we know, oldest first.
+    pub async fn pending_txns(&self) -> Result<Vec<(i64, Txn)>> {
+        let rows = sqlx::query!(
+            "SELECT id, extrinsic_data, kind, sender_index, nonce, dest FROM txns
+             WHERE status IN ('signed', 'submitted') ORDER BY id"
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let kind = row
+                    .kind
+                    .ok_or_else(|| anyhow!("pending txn {} has no kind", row.id))?;
+                let nonce = row
+                    .nonce
+                    .ok_or_else(|| anyhow!("pending txn {} has no nonce", row.id))?;
+                let txn = Txn {
+                    kind: TxnKind::parse(&kind)?,
+                    sender: row.sender_index.map(|index| index as u32),
+                    nonce: nonce as u64,
+                    data: row.extrinsic_data,
+                    dest: row
+                        .dest
+                        .map(|dest| {
+                            <[u8; 32]>::try_from(dest)
+                                .map(AccountId32)
+                                .map_err(|_| anyhow!("pending txn {} has an invalid dest", row.id))
+                        })
+                        .transpose()?,
+                };
+                Ok((row.id, txn))
+            })
+            .collect()
+    }
+
+    pub async fn set_status(&self, id: i64, status: TxnStatus) -> Result<()> {
+        let status = status.as_str();
+        sqlx::query!("UPDATE txns SET status = ? WHERE id = ?", status, id)
+            .execute(&self.sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Updates the status of the transaction with the given hash.
+    pub async fn set_status_by_hash(&self, hash: H256, status: TxnStatus) -> Result<()> {
+        let hash = hash.as_bytes();
+        let status = status.as_str();
+        sqlx::query!("UPDATE txns SET status = ? WHERE hash = ?", status, hash)
+            .execute(&self.sqlite)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the hashes of the transactions that could still be finalized. The failed ones are
+    /// included as well, since they paid the fees all the same. The dropped, invalid and obsolete
+    /// ones are left out, they were given up on.
+    pub async fn unfinalized_txn_hashes(&self) -> Result<HashSet<H256>> {
+        let rows = sqlx::query!(
+            "SELECT hash FROM txns
+             WHERE hash IS NOT NULL AND block_number IS NULL
+               AND status IN ('signed', 'submitted', 'in-best-block', 'confirmed', 'failed')"
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        let hashes = rows
+            .into_iter()
+            .filter_map(|row| row.hash)
+            .map(|hash| H256::from_slice(&hash))
+            .collect();
+        Ok(hashes)
+    }
+
+    /// Marks the given transactions as finalized in the given block and advances `last_block`. The
+    /// failed transactions keep their status.
+    ///
+    /// `time` is the timestamp of the block in milliseconds. `found` holds the hashes of the
+    /// transactions along with their extrinsic indices and the fees they paid, if known.
+    pub async fn record_finalized_block(
+        &self,
+        number: u32,
+        block_hash: H256,
+        time: Option<u64>,
+        found: &[(H256, u32, Option<u128>)],
+    ) -> Result<()> {
+        let mut tx = self.sqlite.begin().await?;
+        let status = TxnStatus::Finalized.as_str();
+        let block_hash = block_hash.as_bytes();
+        let time = time.map(|time| time as i64);
+        for (hash, extrinsic_index, fee) in found {
+            let hash = hash.as_bytes();
+            let fee = fee.map(|fee| fee as i64);
+            sqlx::query!(
+                "UPDATE txns
+                 SET status = CASE WHEN status = 'failed' THEN status ELSE ? END,
+                     block_hash = ?, extrinsic_index = ?, fee = ?, block_number = ?, block_time = ?
+                 WHERE hash = ?",
+                status,
+                block_hash,
+                extrinsic_index,
+                fee,
+                number,
+                time,
+                hash
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let number = number.to_string();
+        sqlx::query!("UPDATE kv SET value = ? WHERE key = 'last_block'", number)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the number of transactions in every status.
+    pub async fn txn_counts(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query!(
+            "SELECT status, COUNT(*) AS count FROM txns GROUP BY status ORDER BY status"
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.status, row.count))
+            .collect())
+    }
+
+    /// Returns the number of the mints that made it on chain successfully.
+    pub async fn mint_count(&self) -> Result<u64> {
+        let row = sqlx::query!(
+            "SELECT COUNT(*) AS count FROM txns
+             WHERE kind = 'mint' AND status IN ('in-best-block', 'finalized', 'confirmed')"
+        )
+        .fetch_one(&self.sqlite)
+        .await?;
+        Ok(row.count as u64)
+    }
+
+    /// Returns the sum of the recorded fees.
+    pub async fn total_fees(&self) -> Result<u128> {
+        let row = sqlx::query!(r#"SELECT SUM(fee) AS "total?: i64" FROM txns"#)
+            .fetch_one(&self.sqlite)
+            .await?;
+        Ok(row.total.unwrap_or(0) as u128)
+    }
+
+    /// Returns the ID of the last recorded transaction, 0 if there are none.
+    pub async fn last_txn_id(&self) -> Result<i64> {
+        let row = sqlx::query!(r#"SELECT MAX(id) AS "id?: i64" FROM txns"#)
+            .fetch_one(&self.sqlite)
+            .await?;
+        Ok(row.id.unwrap_or(0))
+    }
+
+    /// Returns the sum of the fees recorded for the transactions after the given ID.
+    pub async fn fees_since(&self, id: i64) -> Result<u128> {
+        let row = sqlx::query!(
+            r#"SELECT SUM(fee) AS "total?: i64" FROM txns WHERE id > ?"#,
+            id
+        )
+        .fetch_one(&self.sqlite)
+        .await?;
+        Ok(row.total.unwrap_or(0) as u128)
+    }
+
+    /// Returns the costs of the finalized transactions, failed or not, per period, oldest first. `period` is a
+    /// `strftime` format the block times are grouped by, e.g. `%Y-%m-%d` for days.
+    pub async fn costs(&self, period: &str) -> Result<Vec<Costs>> {
+        let rows = sqlx::query!(
+            r#"SELECT strftime(?, block_time / 1000, 'unixepoch') AS "period!: String",
+                      COUNT(*) AS "txns!: i64",
+                      COUNT(fee) AS "with_fee!: i64",
+                      SUM(kind = 'mint' AND status = 'finalized') AS "mints!: i64",
+                      SUM(fee) AS "fees?: i64"
+               FROM txns
+               WHERE block_time IS NOT NULL
+               GROUP BY 1 ORDER BY 1"#,
+            period
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Costs {
+                period: row.period,
+                txns: row.txns as u64,
+                unknown_fees: (row.txns - row.with_fee) as u64,
+                mints: row.mints as u64,
+                fees: row.fees.unwrap_or(0) as u128,
+            })
+            .collect())
+    }
+
+    /// Returns the account the last transfer of the chain that made it on chain went to, if any.
+    pub async fn get_holder(&self) -> Result<Option<AccountRecord>> {
+        let row = sqlx::query!(
+            "SELECT sender_index FROM txns
+             WHERE kind IN ('transfer_all', 'transfer')
+               AND status IN ('in-best-block', 'finalized', 'confirmed')
+             ORDER BY id DESC LIMIT 1"
+        )
+        .fetch_optional(&self.sqlite)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let index = row.sender_index.map_or(0, |index| index + 1);
+        let record = sqlx::query_as!(
+            AccountRecord,
+            r#"SELECT account_index AS "index: u32", address, public_key, derivation_path
+               FROM accounts WHERE account_index = ?"#,
+            index
+        )
+        .fetch_optional(&self.sqlite)
+        .await?;
+        Ok(record)
+    }
+
+    /// Returns the block the ksm-20 indexing started at and the last block indexed, if any.
+    pub async fn get_ksm20_progress(&self) -> Result<(Option<u32>, Option<u32>)> {
+        let from = sqlx::query!("SELECT value FROM kv WHERE key = 'ksm20_from_block'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        let last = sqlx::query!("SELECT value FROM kv WHERE key = 'ksm20_last_block'")
+            .fetch_one(&self.sqlite)
+            .await?;
+        let from = from.value.map(|value| value.parse::<u32>()).transpose()?;
+        let last = last.value.map(|value| value.parse::<u32>()).transpose()?;
+        Ok((from, last))
+    }
+
+    pub async fn set_ksm20_from_block(&self, number: u32) -> Result<()> {
+        let number = number.to_string();
+        sqlx::query!(
+            "UPDATE kv SET value = ? WHERE key = 'ksm20_from_block'",
+            number
+        )
+        .execute(&self.sqlite)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn ksm20_ticks(&self) -> Result<Vec<Ksm20Tick>> {
+        let rows = sqlx::query!(
+            r#"SELECT tick AS "tick!", max, lim, minted, deployer,
+                      block_number AS "block_number: u32"
+               FROM ksm20_ticks ORDER BY tick"#
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(Ksm20Tick {
+                    tick: row.tick,
+                    max: row.max.parse()?,
+                    lim: row.lim.parse()?,
+                    minted: row.minted.parse()?,
+                    deployer: row.deployer,
+                    block_number: row.block_number,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the ksm-20 balance of the address, zero if it never held the tick.
+    pub async fn ksm20_balance(&self, tick: &str, address: &str) -> Result<u128> {
+        let row = sqlx::query!(
+            "SELECT balance FROM ksm20_balances WHERE tick = ? AND address = ?",
+            tick,
+            address
+        )
+        .fetch_optional(&self.sqlite)
+        .await?;
+        match row {
+            Some(row) => Ok(row.balance.parse()?),
+            None => Ok(0),
+        }
+    }
+
+    /// Persists the ticks and the balances changed by the given block and advances
+    /// `ksm20_last_block`.
+    pub async fn record_ksm20_block(
+        &self,
+        number: u32,
+        ticks: &[Ksm20Tick],
+        balances: &[(String, String, u128)],
+    ) -> Result<()> {
+        let mut tx = self.sqlite.begin().await?;
+        for tick in ticks {
+            let max = tick.max.to_string();
+            let lim = tick.lim.to_string();
+            let minted = tick.minted.to_string();
+            sqlx::query!(
+                "INSERT OR REPLACE INTO ksm20_ticks (tick, max, lim, minted, deployer, block_number)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                tick.tick,
+                max,
+                lim,
+                minted,
+                tick.deployer,
+                tick.block_number
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        for (tick, address, balance) in balances {
+            let balance = balance.to_string();
+            sqlx::query!(
+                "INSERT OR REPLACE INTO ksm20_balances (tick, address, balance) VALUES (?, ?, ?)",
+                tick,
+                address,
+                balance
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let number = number.to_string();
+        sqlx::query!(
+            "UPDATE kv SET value = ? WHERE key = 'ksm20_last_block'",
+            number
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the non-zero ksm-20 balances of the derived accounts, optionally of a single tick.
+    pub async fn ksm20_account_balances(&self, tick: Option<&str>) -> Result<Vec<Ksm20Holding>> {
+        let rows = sqlx::query!(
+            r#"SELECT b.tick AS "tick!", a.account_index AS "index!: u32", a.address AS "address!",
+                      b.balance
+               FROM ksm20_balances b JOIN accounts a ON a.address = b.address
+               WHERE (?1 IS NULL OR b.tick = ?1) AND b.balance != '0'
+               ORDER BY b.tick, a.account_index"#,
+            tick
+        )
+        .fetch_all(&self.sqlite)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(Ksm20Holding {
+                    tick: row.tick,
+                    index: row.index,
+                    address: row.address,
+                    balance: row.balance.parse()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Records that the transaction with the given hash was included but failed to dispatch.
+    pub async fn set_failed(&self, hash: H256, error: &str) -> Result<()> {
+        let hash = hash.as_bytes();
+        let status = TxnStatus::Failed.as_str();
+        sqlx::query!(
+            "UPDATE txns SET status = ?, error = ? WHERE hash = ?",
+            status,
+            error,
+            hash
+        )
+        .execute(&self.sqlite)
+        .await?;
+        Ok(())
+    }
+
+    /// Records that the transaction with the given hash was included in a block.
+    pub async fn set_included(
+        &self,
+        hash: H256,
+        status: TxnStatus,
+        block_hash: H256,
+        extrinsic_index: Option<u32>,
+    ) -> Result<()> {
+        let hash = hash.as_bytes();
+        let status = status.as_str();
+        let block_hash = block_hash.as_bytes();
+        sqlx::query!(
+            "UPDATE txns SET status = ?, block_hash = ?, extrinsic_index = ? WHERE hash = ?",
+            status,
+            block_hash,
+            extrinsic_index,
+            hash
+        )
+        .execute(&self.sqlite)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The costs of the finalized transactions over a period of time.
+#[derive(serde::Serialize)]
+pub struct Costs {
+    /// The period, or the whole run for the totals.
+    pub period: String,
+    pub txns: u64,
+    /// The number of transactions whose fees weren't recorded.
+    pub unknown_fees: u64,
+    /// The number of successful mints.
+    pub mints: u64,
+    pub fees: u128,
+}
+
+/// A deployed ksm-20 tick. See the `ksm20_ticks` table.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Ksm20Tick {
+    pub tick: String,
+    pub max: u128,
+    pub lim: u128,
+    pub minted: u128,
+    pub deployer: String,
+    pub block_number: u32,
+}
+
+/// The ksm-20 balance of a derived account.
+#[derive(serde::Serialize)]
+pub struct Ksm20Holding {
+    pub tick: String,
+    pub index: u32,
+    pub address: String,
+    pub balance: u128,
+}
+
+/// A derived account. See the `accounts` table.
+pub struct AccountRecord {
+    pub index: u32,
+    pub address: String,
+    pub public_key: Vec<u8>,
+    pub derivation_path: String,
+}
+
+impl AccountRecord {
+    async fn insert(&self, tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO accounts (account_index, address, public_key, derivation_path)
+             VALUES (?, ?, ?, ?)",
+            self.index,
+            self.address,
+            self.public_key,
+            self.derivation_path
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The status of a persisted transaction. See the `txns` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxnStatus {
+    Signed,
+    Submitted,
+    InBestBlock,
+    Finalized,
+    Dropped,
+    Invalid,
+    Failed,
+    Confirmed,
+    Obsolete,
+}
+
+impl TxnStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxnStatus::Signed => "signed",
+            TxnStatus::Submitted => "submitted",
+            TxnStatus::InBestBlock => "in-best-block",
+            TxnStatus::Finalized => "finalized",
+            TxnStatus::Dropped => "dropped",
+            TxnStatus::Invalid => "invalid",
+            TxnStatus::Failed => "failed",
+            TxnStatus::Confirmed => "confirmed",
+            TxnStatus::Obsolete => "obsolete",
+        }
+    }
+}
+
+/// What a persisted transaction does. See the `txns` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxnKind {
+    TransferAll,
+    /// A `transfer_keep_alive` of a hop that leaves a reserve in the sender.
+    Transfer,
+    Mint,
+    /// A `transfer_all` that moves the leftovers of a derived account out of the chain.
+    Sweep,
+}
+
+impl TxnKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxnKind::TransferAll => "transfer_all",
+            TxnKind::Transfer => "transfer",
+            TxnKind::Mint => "mint",
+            TxnKind::Sweep => "sweep",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "transfer_all" => Ok(TxnKind::TransferAll),
+            "transfer" => Ok(TxnKind::Transfer),
+            "mint" => Ok(TxnKind::Mint),
+            "sweep" => Ok(TxnKind::Sweep),
+            _ => bail!("unknown txn kind: {}", s),
+        }
+    }
+}
@@ -0,0 +1,564 @@
+//! Computing the ksm-20 balances from the remarks on chain.
+//!
+//! The finalized Kusama blocks are walked one by one and the successful ksm-20 remarks are applied
+//! in the order they made it on chain: the first deploy of a tick wins, a mint is ignored if it
+//! exceeds the limit of the tick and gets cut at the max supply, and a transfer is ignored unless
+//! the sender holds the amount. The state is persisted in the `ksm20_*` tables after every block,
+//! so the indexing could be resumed.
+//!
+//! The remarks are decoded with the generated metadata, hence only Kusama could be indexed.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use subxt::{
+    backend::{legacy::LegacyRpcMethods, rpc::RpcClient},
+    blocks::Block,
+    config::Hasher,
+    ext::codec::Decode,
+    utils::{AccountId32, MultiAddress},
+};
+
+use crate::{
+    db::{Database, Ksm20Holding, Ksm20Tick},
+    inscription::token::{self, Op, Token},
+    metadata::{
+        self,
+        kusama::{
+            self,
+            runtime_types::{
+                frame_system::pallet::Call as SystemCall,
+                pallet_utility::pallet::Call as UtilityCall,
+            },
+        },
+        Chain,
+    },
+    Cli,
+};
+
+type IndexedBlock = Block<metadata::Config, metadata::Client>;
+
+#[derive(clap::Args, Debug)]
+pub struct IndexArgs {
+    /// The block to start at. It must not be later than the deploys of the ticks of interest.
+    /// Only needed on the first run, the later ones continue where the last one stopped.
+    ///
+    /// The blocks are decoded with the current metadata, so the remarks in the blocks from before a
+    /// runtime upgrade that changed the calls could be skipped without notice.
+    #[clap(long, value_name = "BLOCK")]
+    from: Option<u32>,
+    /// The block to stop at. Defaults to the latest finalized one.
+    #[clap(long, value_name = "BLOCK")]
+    to: Option<u32>,
+    /// Only report the balances indexed so far, without indexing any further.
+    #[clap(long)]
+    no_sync: bool,
+    /// Report the balances of the given tick only.
+    #[clap(long)]
+    tick: Option<String>,
+    /// Print the balances as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    last_block: Option<u32>,
+    ticks: Vec<Ksm20Tick>,
+    /// The balances of the derived accounts.
+    holdings: Vec<Ksm20Holding>,
+}
+
+pub async fn run(cli: &Cli, args: &IndexArgs) -> Result<()> {
+    let chain = cli.chain()?;
+    check_chain(chain)?;
+    let db = Database::new().await?;
+    if !args.no_sync {
+        sync(cli, chain, &db, args).await?;
+    }
+
+    let tick = args.tick.as_ref().map(|tick| tick.to_lowercase());
+    let holdings = db.ksm20_account_balances(tick.as_deref()).await?;
+    let held = holdings
+        .iter()
+        .map(|holding| holding.tick.as_str())
+        .collect::<HashSet<_>>();
+    let ticks = db
+        .ksm20_ticks()
+        .await?
+        .into_iter()
+        .filter(|t| held.contains(t.tick.as_str()) || tick.as_ref() == Some(&t.tick))
+        .collect::<Vec<_>>();
+    let report = Report {
+        last_block: db.get_ksm20_progress().await?.1,
+        ticks,
+        holdings,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    match report.last_block {
+        Some(last_block) => println!("indexed up to #{}", last_block),
+        None => println!("nothing indexed yet"),
+    }
+    if let Some(tick) = &tick {
+        if report.ticks.is_empty() {
+            println!("{} is not deployed", tick);
+        }
+    }
+    for tick in &report.ticks {
+        let (total, accounts) = report
+            .holdings
+            .iter()
+            .filter(|holding| holding.tick == tick.tick)
+            .fold((0, 0), |(total, accounts), holding| {
+                (total + holding.balance, accounts + 1)
+            });
+        println!(
+            "{}: {} held by {} derived accounts (minted {} of {}, {} per mint)",
+            tick.tick, total, accounts, tick.minted, tick.max, tick.lim
+        );
+    }
+    Ok(())
+}
+
+/// Indexes the finalized blocks from where the last run stopped, or from `--from`.
+async fn sync(cli: &Cli, chain: Chain, db: &Database, args: &IndexArgs) -> Result<()> {
+    let start = match db.get_ksm20_progress().await? {
+        (Some(from), _) if args.from.is_some_and(|requested| requested != from) => bail!(
+            "The index was started at #{}, it could not be restarted at another block",
+            from
+        ),
+        (Some(from), last) => last.map_or(from, |last| last + 1),
+        (None, _) => {
+            let from = args
+                .from
+                .ok_or_else(|| anyhow!("--from is required on the first run"))?;
+            db.set_ksm20_from_block(from).await?;
+            from
+        }
+    };
+
+    let endpoint = chain.pick_endpoint(cli.endpoint.as_deref());
+    let mut indexer = Indexer::connect(endpoint, chain, db).await?;
+    let finalized = indexer.finalized_number().await?;
+    let end = match args.to {
+        Some(to) if to > finalized => bail!("#{} is not finalized yet", to),
+        Some(to) => to,
+        None => finalized,
+    };
+    if start > end {
+        println!("already indexed up to #{}", start - 1);
+        return Ok(());
+    }
+    println!("indexing #{}..=#{}", start, end);
+    indexer.index(db, start, end).await
+}
+
+/// Applies the ksm-20 remarks of the finalized blocks to the state in the database.
+pub struct Indexer {
+    chain: Chain,
+    client: metadata::Client,
+    legacy: LegacyRpcMethods<metadata::Config>,
+    state: State,
+}
+
+impl Indexer {
+    pub async fn connect(endpoint: &str, chain: Chain, db: &Database) -> Result<Self> {
+        check_chain(chain)?;
+        let rpc_client = RpcClient::from_url(endpoint).await?;
+        let client = metadata::Client::from_rpc_client(rpc_client.clone()).await?;
+        let legacy = LegacyRpcMethods::<metadata::Config>::new(rpc_client);
+        db.check_genesis_hash(client.genesis_hash()).await?;
+        if !metadata::validate_static(&client) {
+            println!(
+                "the generated metadata doesn't match the chain, some remarks might be missed"
+            );
+        }
+        Ok(Indexer {
+            chain,
+            client,
+            legacy,
+            state: State::load(db).await?,
+        })
+    }
+
+    async fn finalized_number(&self) -> Result<u32> {
+        let finalized = self.client.backend().latest_finalized_block_ref().await?;
+        Ok(self.client.blocks().at(finalized.hash()).await?.number())
+    }
+
+    /// Indexes the blocks finalized since the last indexed one and returns the number of the
+    /// latest. The indexing must have been started with the `index` command.
+    pub async fn catch_up(&mut self, db: &Database) -> Result<u32> {
+        let (Some(from), last) = db.get_ksm20_progress().await? else {
+            bail!("Nothing is indexed yet, run the index command with --from first");
+        };
+        let finalized = self.finalized_number().await?;
+        self.index(db, last.map_or(from, |last| last + 1), finalized)
+            .await?;
+        Ok(finalized)
+    }
+
+    /// Returns the state of the deployed tick.
+    pub fn tick(&self, tick: &str) -> Option<&Ksm20Tick> {
+        self.state.ticks.get(&tick.to_lowercase())
+    }
+
+    async fn index(&mut self, db: &Database, start: u32, end: u32) -> Result<()> {
+        for number in start..=end {
+            let hash = self
+                .legacy
+                .chain_get_block_hash(Some(number.into()))
+                .await?
+                .ok_or_else(|| anyhow!("block #{} not found", number))?;
+            let block = self.client.blocks().at(hash).await?;
+            let ops = block_ops(self.chain, &block).await?;
+            let mut applied = 0;
+            for (sender, token) in &ops {
+                if self
+                    .state
+                    .apply(db, self.chain, number, sender, token.op())
+                    .await?
+                {
+                    applied += 1;
+                }
+            }
+            self.state.commit(db, number).await?;
+            if !ops.is_empty() {
+                println!("#{}: {} ksm-20 ops, {} applied", number, ops.len(), applied);
+            } else if number % 1000 == 0 {
+                println!("indexed up to #{}", number);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_chain(chain: Chain) -> Result<()> {
+    if !chain.has_static_metadata() {
+        bail!("Only Kusama could be indexed, the remarks are decoded with the generated metadata");
+    }
+    Ok(())
+}
+
+/// Returns the ksm-20 remarks that made it on chain in the block along with their senders, in
+/// order.
+async fn block_ops(chain: Chain, block: &IndexedBlock) -> Result<Vec<(String, Token)>> {
+    let mut ops = Vec::new();
+    for ext in block.extrinsics().await?.iter() {
+        let ext = ext?;
+        if !matches!(ext.pallet_name()?, "System" | "Utility") {
+            continue;
+        }
+        let Some(mut address) = ext.address_bytes() else {
+            continue;
+        };
+        let Ok(MultiAddress::Id(sender)) = MultiAddress::<AccountId32, ()>::decode(&mut address)
+        else {
+            continue;
+        };
+        // The calls that don't match the generated metadata are not the remarks we know of.
+        let Ok(call) = ext.as_root_extrinsic::<kusama::Call>() else {
+            continue;
+        };
+        let mut remarks = Vec::new();
+        collect_remarks(call, &mut remarks);
+        if remarks.is_empty() {
+            continue;
+        }
+
+        let events = ext.events().await?;
+        if !events.has::<kusama::system::events::ExtrinsicSuccess>()? {
+            continue;
+        }
+        // A batch succeeds even if some of its calls failed. The remarks with events could be
+        // confirmed one by one, the plain ones only count if every call succeeded.
+        let all_succeeded = !events.has::<kusama::utility::events::BatchInterrupted>()?
+            && !events.has::<kusama::utility::events::BatchCompletedWithErrors>()?;
+        let mut remarked = Vec::new();
+        for event in events.find::<kusama::system::events::Remarked>() {
+            remarked.push(event?.hash);
+        }
+        let sender = chain.to_ss58(&sender);
+        for (remark, with_event) in remarks {
+            if with_event {
+                let hash = <metadata::Config as subxt::Config>::Hasher::hash(&remark);
+                match remarked.iter().position(|remarked| *remarked == hash) {
+                    Some(position) => {
+                        remarked.remove(position);
+                    }
+                    None => continue,
+                }
+            } else if !all_succeeded {
+                continue;
+            }
+            if let Ok(token) = Token::parse(token::KSM20, &remark) {
+                ops.push((sender.clone(), token));
+            }
+        }
+    }
+    Ok(ops)
+}
+
+/// Collects the remarks made by the call along with whether they emit `Remarked`, looking into
+/// the batches. The derivative calls are dispatched from other accounts and thus skipped.
+fn collect_remarks(call: kusama::Call, remarks: &mut Vec<(Vec<u8>, bool)>) {
+    match call {
+        kusama::Call::System(SystemCall::remark { remark }) => remarks.push((remark, false)),
+        kusama::Call::System(SystemCall::remark_with_event { remark }) => {
+            remarks.push((remark, true))
+        }
+        kusama::Call::Utility(
+            UtilityCall::batch { calls }
+            | UtilityCall::batch_all { calls }
+            | UtilityCall::force_batch { calls },
+        ) => {
+            for call in calls {
+                collect_remarks(call, remarks);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The ticks along with the balances changed by the block being indexed.
+struct State {
+    ticks: HashMap<String, Ksm20Tick>,
+    changed_ticks: HashSet<String>,
+    /// The balances by the tick and the address. Only the ones touched by the current block.
+    balances: BTreeMap<(String, String), u128>,
+}
+
+impl State {
+    async fn load(db: &Database) -> Result<Self> {
+        let ticks = db
+            .ksm20_ticks()
+            .await?
+            .into_iter()
+            .map(|tick| (tick.tick.clone(), tick))
+            .collect();
+        Ok(State {
+            ticks,
+            changed_ticks: HashSet::new(),
+            balances: BTreeMap::new(),
+        })
+    }
+
+    async fn balance(&mut self, db: &Database, tick: &str, address: &str) -> Result<u128> {
+        let key = (tick.to_string(), address.to_string());
+        if let Some(balance) = self.balances.get(&key) {
+            return Ok(*balance);
+        }
+        let balance = db.ksm20_balance(tick, address).await?;
+        self.balances.insert(key, balance);
+        Ok(balance)
+    }
+
+    fn set_balance(&mut self, tick: &str, address: &str, balance: u128) {
+        self.balances
+            .insert((tick.to_string(), address.to_string()), balance);
+    }
+
+    /// Applies the operation, returns whether it had any effect.
+    async fn apply(
+        &mut self,
+        db: &Database,
+        chain: Chain,
+        number: u32,
+        sender: &str,
+        op: &Op,
+    ) -> Result<bool> {
+        match op {
+            Op::Deploy { tick, max, lim } => {
+                let tick = tick.to_lowercase();
+                if self.ticks.contains_key(&tick) {
+                    return Ok(false);
+                }
+                let deployed = Ksm20Tick {
+                    tick: tick.clone(),
+                    max: *max,
+                    lim: *lim,
+                    minted: 0,
+                    deployer: sender.to_string(),
+                    block_number: number,
+                };
+                self.ticks.insert(tick.clone(), deployed);
+                self.changed_ticks.insert(tick);
+            }
+            Op::Mint { tick, amt } => {
+                let tick = tick.to_lowercase();
+                let Some(deployed) = self.ticks.get_mut(&tick) else {
+                    return Ok(false);
+                };
+                // The ksm-20 mints always have the amount, only dot-20 leaves it to the deploy.
+                let amt = amt.unwrap_or(deployed.lim);
+                if amt > deployed.lim {
+                    return Ok(false);
+                }
+                let amt = amt.min(deployed.max - deployed.minted);
+                if amt == 0 {
+                    return Ok(false);
+                }
+                deployed.minted += amt;
+                self.changed_ticks.insert(tick.clone());
+                let balance = self.balance(db, &tick, sender).await?;
+                self.set_balance(&tick, sender, balance + amt);
+            }
+            Op::Transfer { tick, amt, to } => {
+                let tick = tick.to_lowercase();
+                if !self.ticks.contains_key(&tick) {
+                    return Ok(false);
+                }
+                let balance = self.balance(db, &tick, sender).await?;
+                if balance < *amt {
+                    return Ok(false);
+                }
+                let to = AccountId32::from_str(to)
+                    .map_err(|e| anyhow!("Invalid transfer destination {}: {:?}", to, e))?;
+                let to = chain.to_ss58(&to);
+                self.set_balance(&tick, sender, balance - amt);
+                let to_balance = self.balance(db, &tick, &to).await?;
+                self.set_balance(&tick, &to, to_balance + amt);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Persists the changes of the block.
+    async fn commit(&mut self, db: &Database, number: u32) -> Result<()> {
+        let ticks = self
+            .changed_ticks
+            .drain()
+            .map(|tick| self.ticks[&tick].clone())
+            .collect::<Vec<_>>();
+        let balances = std::mem::take(&mut self.balances)
+            .into_iter()
+            .map(|((tick, address), balance)| (tick, address, balance))
+            .collect::<Vec<_>>();
+        db.record_ksm20_block(number, &ticks, &balances).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const BOB: &str = "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty";
+
+    struct Index {
+        db: Database,
+        state: State,
+        number: u32,
+    }
+
+    impl Index {
+        async fn new() -> Self {
+            let db = Database::open("sqlite::memory:").await.unwrap();
+            let state = State::load(&db).await.unwrap();
+            Index {
+                db,
+                state,
+                number: 1,
+            }
+        }
+
+        /// Applies the remark as if it was made by the sender in a block of its own.
+        async fn apply(&mut self, sender: &str, remark: &str) -> bool {
+            let token = Token::parse(token::KSM20, remark.as_bytes()).unwrap();
+            let sender = address(sender);
+            let applied = self
+                .state
+                .apply(&self.db, Chain::Kusama, self.number, &sender, token.op())
+                .await
+                .unwrap();
+            self.state.commit(&self.db, self.number).await.unwrap();
+            self.number += 1;
+            applied
+        }
+
+        async fn balance(&self, tick: &str, holder: &str) -> u128 {
+            self.db.ksm20_balance(tick, &address(holder)).await.unwrap()
+        }
+
+        async fn tick(&self, tick: &str) -> Ksm20Tick {
+            let ticks = self.db.ksm20_ticks().await.unwrap();
+            ticks.into_iter().find(|t| t.tick == tick).unwrap()
+        }
+    }
+
+    /// The Kusama address, as the senders are recorded.
+    fn address(ss58: &str) -> String {
+        Chain::Kusama.to_ss58(&AccountId32::from_str(ss58).unwrap())
+    }
+
+    fn mint(tick: &str, amt: u128) -> String {
+        format!(
+            r#"{{"p":"ksm-20","op":"mint","tick":"{}","amt":"{}"}}"#,
+            tick, amt
+        )
+    }
+
+    fn transfer(amt: u128, to: &str) -> String {
+        format!(
+            r#"{{"p":"ksm-20","op":"transfer","tick":"sama","amt":"{}","to":"{}"}}"#,
+            amt, to
+        )
+    }
+
+    const DEPLOY: &str = r#"{"p":"ksm-20","op":"deploy","tick":"SAMA","max":"25","lim":"10"}"#;
+
+    #[tokio::test]
+    async fn first_deploy_wins() {
+        let mut index = Index::new().await;
+        assert!(!index.apply(ALICE, &mint("sama", 10)).await);
+        assert!(index.apply(ALICE, DEPLOY).await);
+        let redeploy = r#"{"p":"ksm-20","op":"deploy","tick":"sama","max":"1000","lim":"1000"}"#;
+        assert!(!index.apply(BOB, redeploy).await);
+        let sama = index.tick("sama").await;
+        assert_eq!((sama.max, sama.lim, sama.minted), (25, 10, 0));
+        assert_eq!(sama.deployer, address(ALICE));
+        assert_eq!(sama.block_number, 2);
+    }
+
+    #[tokio::test]
+    async fn mints() {
+        let mut index = Index::new().await;
+        index.apply(ALICE, DEPLOY).await;
+        // Above the limit.
+        assert!(!index.apply(ALICE, &mint("sama", 11)).await);
+        // The ticks are case-insensitive.
+        assert!(index.apply(ALICE, &mint("Sama", 10)).await);
+        assert!(index.apply(BOB, &mint("SAMA", 10)).await);
+        // Cut at the max supply, after which nothing is minted anymore.
+        assert!(index.apply(BOB, &mint("sama", 10)).await);
+        assert!(!index.apply(ALICE, &mint("sama", 1)).await);
+        assert_eq!(index.tick("sama").await.minted, 25);
+        assert_eq!(index.balance("sama", ALICE).await, 10);
+        assert_eq!(index.balance("sama", BOB).await, 15);
+    }
+
+    #[tokio::test]
+    async fn transfers() {
+        let mut index = Index::new().await;
+        index.apply(ALICE, DEPLOY).await;
+        index.apply(ALICE, &mint("sama", 10)).await;
+        assert!(!index.apply(ALICE, &transfer(11, BOB)).await);
+        assert!(!index.apply(BOB, &transfer(1, ALICE)).await);
+        assert!(index.apply(ALICE, &transfer(4, BOB)).await);
+        assert_eq!(index.balance("sama", ALICE).await, 6);
+        assert_eq!(index.balance("sama", BOB).await, 4);
+        assert!(index.apply(ALICE, &transfer(6, BOB)).await);
+        assert_eq!(index.balance("sama", ALICE).await, 0);
+        assert_eq!(index.balance("sama", BOB).await, 10);
+        // Transferring doesn't mint.
+        assert_eq!(index.tick("sama").await.minted, 10);
+    }
+}
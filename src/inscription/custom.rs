@@ -0,0 +1,265 @@
+//! The protocols described by spec files, so that a new JSON format doesn't need a new build.
+//!
+//! A spec names the protocol and lists the fields of the JSON object in their canonical order.
+//! Every field is either a constant or of one of the types: `string`, `number`, `amount` (a
+//! positive integer as a decimal string) or `address` (SS58):
+//!
+//! ```json
+//! {
+//!     "name": "sama-20",
+//!     "fields": [
+//!         { "name": "p", "const": "sama-20" },
+//!         { "name": "op", "const": "mint" },
+//!         { "name": "tick", "type": "string" },
+//!         { "name": "amt", "type": "amount" },
+//!         { "name": "memo", "type": "string", "optional": true }
+//!     ]
+//! }
+//! ```
+//!
+//! The inscription is given with `--remark`, where the constants could be left out.
+
+use std::{collections::HashSet, path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use subxt::utils::AccountId32;
+
+use super::{token::parse_amount, Builder, InscriptionArgs, InscriptionProtocol};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    name: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Field {
+    name: String,
+    #[serde(rename = "const")]
+    constant: Option<Value>,
+    #[serde(rename = "type")]
+    kind: Option<FieldKind>,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum FieldKind {
+    String,
+    Number,
+    Amount,
+    Address,
+}
+
+impl Spec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the protocol spec {}", path.display()))?;
+        let spec: Spec = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid protocol spec {}", path.display()))?;
+        if spec.name.is_empty() {
+            bail!("The protocol spec {} has no name", path.display());
+        }
+        let mut names = HashSet::new();
+        for field in &spec.fields {
+            if !names.insert(field.name.as_str()) {
+                bail!("Field {} of {} is listed twice", field.name, spec.name);
+            }
+            if field.constant.is_some() == field.kind.is_some() {
+                bail!(
+                    "Field {} of {} must have either a const or a type",
+                    field.name,
+                    spec.name
+                );
+            }
+        }
+        Ok(spec)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn builder(self) -> Builder {
+        let spec = Arc::new(self);
+        Box::new(move |args| Ok(Box::new(Custom::from_args(spec.clone(), args)?)))
+    }
+
+    /// Checks the JSON object against the fields.
+    fn check(&self, object: &Map<String, Value>) -> Result<()> {
+        if let Some(key) = object
+            .keys()
+            .find(|key| self.fields.iter().all(|field| &field.name != *key))
+        {
+            bail!("{} doesn't have field {}", self.name, key);
+        }
+        for field in &self.fields {
+            let Some(value) = object.get(&field.name) else {
+                if field.optional {
+                    continue;
+                }
+                bail!("{} requires field {}", self.name, field.name);
+            };
+            let valid = match (&field.constant, field.kind) {
+                (Some(constant), _) => value == constant,
+                (None, Some(FieldKind::String)) => value.is_string(),
+                (None, Some(FieldKind::Number)) => value.is_number(),
+                (None, Some(FieldKind::Amount)) => {
+                    value.as_str().is_some_and(|v| parse_amount(v).is_ok())
+                }
+                (None, Some(FieldKind::Address)) => value
+                    .as_str()
+                    .is_some_and(|v| AccountId32::from_str(v).is_ok()),
+                (None, None) => unreachable!("checked when loading"),
+            };
+            if !valid {
+                bail!("Invalid {} field {}: {}", self.name, field.name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An inscription of a protocol loaded from a spec.
+pub struct Custom {
+    spec: Arc<Spec>,
+    object: Map<String, Value>,
+}
+
+impl Custom {
+    fn from_args(spec: Arc<Spec>, args: &InscriptionArgs) -> Result<Self> {
+        let mut object: Map<String, Value> = serde_json::from_str(args.raw_remark()?)
+            .with_context(|| format!("Invalid {} remark", spec.name))?;
+        for field in &spec.fields {
+            if let Some(constant) = &field.constant {
+                object
+                    .entry(field.name.clone())
+                    .or_insert_with(|| constant.clone());
+            }
+        }
+        spec.check(&object)?;
+        Ok(Custom { spec, object })
+    }
+}
+
+impl InscriptionProtocol for Custom {
+    fn name(&self) -> &str {
+        &self.spec.name
+    }
+
+    /// Returns the JSON with the fields in the order of the spec.
+    fn remark(&self) -> Vec<u8> {
+        let fields = self
+            .spec
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let value = self.object.get(&field.name)?;
+                Some(format!("{}:{}", Value::String(field.name.clone()), value))
+            })
+            .collect::<Vec<_>>();
+        format!("{{{}}}", fields.join(",")).into_bytes()
+    }
+
+    fn validate(&self, remark: &[u8]) -> Result<()> {
+        let object: Map<String, Value> = serde_json::from_slice(remark)
+            .with_context(|| format!("Invalid {} remark", self.spec.name))?;
+        self.spec.check(&object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inscription::tests::args;
+
+    const SPEC: &str = r#"{
+        "name": "sama-20",
+        "fields": [
+            { "name": "p", "const": "sama-20" },
+            { "name": "op", "const": "mint" },
+            { "name": "tick", "type": "string" },
+            { "name": "amt", "type": "amount" },
+            { "name": "n", "type": "number", "optional": true },
+            { "name": "to", "type": "address", "optional": true }
+        ]
+    }"#;
+
+    /// Writes the spec into a file of its own and loads it.
+    fn load(name: &str, spec: &str) -> Result<Spec> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // The tests run in parallel.
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "inscriber9000-{}-{}-{}.json",
+            std::process::id(),
+            name,
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, spec).unwrap();
+        let spec = Spec::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        spec
+    }
+
+    fn remark(remark: &str) -> Result<String> {
+        let spec = Arc::new(load("remark", SPEC)?);
+        let custom =
+            Custom::from_args(spec, &args(&["--protocol", "sama-20", "--remark", remark]))?;
+        let remark = custom.remark();
+        custom.validate(&remark)?;
+        Ok(String::from_utf8(remark).unwrap())
+    }
+
+    #[test]
+    fn invalid_specs() {
+        assert!(load("no-name", r#"{ "name": "", "fields": [] }"#).is_err());
+        let twice = r#"{ "name": "x", "fields": [
+            { "name": "p", "const": "x" }, { "name": "p", "type": "string" }
+        ] }"#;
+        assert!(load("twice", twice).is_err());
+        let both =
+            r#"{ "name": "x", "fields": [{ "name": "p", "const": "x", "type": "string" }] }"#;
+        assert!(load("both", both).is_err());
+        let neither = r#"{ "name": "x", "fields": [{ "name": "p" }] }"#;
+        assert!(load("neither", neither).is_err());
+        let unknown = r#"{ "name": "x", "fields": [], "version": 1 }"#;
+        assert!(load("unknown", unknown).is_err());
+    }
+
+    #[test]
+    fn field_order() {
+        // The constants are filled in and everything is put in the order of the spec.
+        assert_eq!(
+            remark(r#"{"n":1,"amt":"5","tick":"sama"}"#).unwrap(),
+            r#"{"p":"sama-20","op":"mint","tick":"sama","amt":"5","n":1}"#
+        );
+        assert_eq!(
+            remark(r#"{"amt":"5","tick":"sama","op":"mint","p":"sama-20"}"#).unwrap(),
+            r#"{"p":"sama-20","op":"mint","tick":"sama","amt":"5"}"#
+        );
+    }
+
+    #[test]
+    fn check() {
+        let alice = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+        assert!(remark(&format!(r#"{{"tick":"sama","amt":"5","to":"{}"}}"#, alice)).is_ok());
+        // A missing or an unknown field.
+        assert!(remark(r#"{"tick":"sama"}"#).is_err());
+        assert!(remark(r#"{"tick":"sama","amt":"5","memo":"gm"}"#).is_err());
+        // A different constant.
+        assert!(remark(r#"{"p":"ksm-20","tick":"sama","amt":"5"}"#).is_err());
+        // Of the wrong type.
+        assert!(remark(r#"{"tick":1,"amt":"5"}"#).is_err());
+        assert!(remark(r#"{"tick":"sama","amt":5}"#).is_err());
+        assert!(remark(r#"{"tick":"sama","amt":"05"}"#).is_err());
+        assert!(remark(r#"{"tick":"sama","amt":"5","n":"1"}"#).is_err());
+        assert!(remark(r#"{"tick":"sama","amt":"5","to":"alice"}"#).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+//! The inscriptions, i.e. what the mints put on chain with `System.remark_with_event`.
+//!
+//! Anything that doesn't follow the protocol is ignored by the indexers, yet the fees are paid all
+//! the same, so the remarks are validated before signing. Besides the built-in protocols, new ones
+//! could be described by spec files, see [`custom`].
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+mod custom;
+mod raw;
+pub mod token;
+
+/// An inscription of some protocol, ready to be put on chain.
+pub trait InscriptionProtocol: Send + Sync {
+    /// The name of the protocol, e.g. `ksm-20`.
+    fn name(&self) -> &str;
+
+    /// Returns the remark to inscribe.
+    fn remark(&self) -> Vec<u8>;
+
+    /// Checks that the remark follows the protocol.
+    fn validate(&self, remark: &[u8]) -> Result<()>;
+
+    /// Whether it makes sense to inscribe the same remark from every account.
+    fn is_repeatable(&self) -> bool {
+        true
+    }
+
+    /// The tick whose supply the inscription mints, if the protocol has a supply at all.
+    fn minted_tick(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Builds an inscription of a protocol from the command line.
+pub type Builder = Box<dyn Fn(&InscriptionArgs) -> Result<Box<dyn InscriptionProtocol>>>;
+
+/// The protocols by their names.
+pub struct Registry {
+    builders: BTreeMap<String, Builder>,
+}
+
+impl Registry {
+    /// Returns the registry of the built-in protocols.
+    pub fn builtin() -> Self {
+        let mut registry = Registry {
+            builders: BTreeMap::new(),
+        };
+        let builtins: [(&str, Builder); 5] = [
+            (
+                token::KSM20,
+                Box::new(|args| Ok(Box::new(token::Token::from_args(token::KSM20, args)?))),
+            ),
+            (
+                token::DOT20,
+                Box::new(|args| Ok(Box::new(token::Token::from_args(token::DOT20, args)?))),
+            ),
+            (
+                raw::JSON,
+                Box::new(|args| Ok(Box::new(raw::Json::from_args(args)?))),
+            ),
+            (
+                raw::TEXT,
+                Box::new(|args| Ok(Box::new(raw::Text::from_args(args)?))),
+            ),
+            (
+                raw::BINARY,
+                Box::new(|args| Ok(Box::new(raw::Binary::from_args(args)?))),
+            ),
+        ];
+        for (name, builder) in builtins {
+            registry
+                .register(name, builder)
+                .expect("the built-in protocols are distinct");
+        }
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, builder: Builder) -> Result<()> {
+        if self.builders.contains_key(name) {
+            bail!("Protocol {} is already registered", name);
+        }
+        self.builders.insert(name.to_string(), builder);
+        Ok(())
+    }
+
+    /// Builds the inscription selected with `--protocol` and checks it against its protocol.
+    pub fn build(&self, args: &InscriptionArgs) -> Result<Box<dyn InscriptionProtocol>> {
+        let builder = self.builders.get(&args.protocol).ok_or_else(|| {
+            let names = self.builders.keys().cloned().collect::<Vec<_>>();
+            anyhow!(
+                "Unknown protocol {}, expected one of {}",
+                args.protocol,
+                names.join(", ")
+            )
+        })?;
+        let inscription = builder(args)?;
+        inscription.validate(&inscription.remark())?;
+        Ok(inscription)
+    }
+}
+
+/// The inscription to mint.
+#[derive(clap::Args, Debug)]
+pub struct InscriptionArgs {
+    /// The protocol of the inscription: `ksm-20`, `dot-20`, `json`, `text`, `binary` or one loaded
+    /// with `--protocol-file`.
+    #[clap(long, default_value = token::KSM20)]
+    protocol: String,
+    /// Registers the protocol described by the JSON spec file. Could be given several times.
+    #[clap(long, value_name = "PATH")]
+    protocol_file: Vec<PathBuf>,
+    /// The remark, e.g. `{"p":"ksm-20","op":"mint","tick":"sama","amt":"1000"}`. Hex for the
+    /// `binary` protocol.
+    #[clap(long, conflicts_with = "op")]
+    remark: Option<String>,
+    /// The ksm-20 or dot-20 operation, along with `--tick` and its fields.
+    #[clap(long, value_enum, requires = "tick")]
+    op: Option<token::OpKind>,
+    #[clap(long, requires = "op")]
+    tick: Option<String>,
+    /// The amount to mint or transfer. The dot-20 mints could leave it out.
+    #[clap(long, requires = "op")]
+    amt: Option<String>,
+    /// The max supply of the deployed token.
+    #[clap(long, requires = "op")]
+    max: Option<String>,
+    /// The limit per mint of the deployed token.
+    #[clap(long, requires = "op")]
+    lim: Option<String>,
+    /// The SS58 address to transfer to.
+    #[clap(long, requires = "op")]
+    to: Option<String>,
+}
+
+impl InscriptionArgs {
+    /// Builds the inscription with the built-in protocols along with the ones from the spec files.
+    pub fn inscription(&self) -> Result<Box<dyn InscriptionProtocol>> {
+        let mut registry = Registry::builtin();
+        for path in &self.protocol_file {
+            let spec = custom::Spec::load(path)?;
+            let name = spec.name().to_string();
+            registry.register(&name, spec.builder())?;
+        }
+        registry.build(self)
+    }
+
+    /// Returns `--remark` for the protocols that don't have the typed flags.
+    fn raw_remark(&self) -> Result<&str> {
+        if self.op.is_some() {
+            bail!("--op is only supported by ksm-20 and dot-20");
+        }
+        self.remark
+            .as_deref()
+            .ok_or_else(|| anyhow!("--remark is required by {}", self.protocol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: InscriptionArgs,
+    }
+
+    /// Parses the inscription flags as given on the command line.
+    pub(super) fn args(flags: &[&str]) -> InscriptionArgs {
+        Cli::parse_from(std::iter::once("inscriber9000").chain(flags.iter().copied())).args
+    }
+
+    fn remark(flags: &[&str]) -> Result<String> {
+        let inscription = args(flags).inscription()?;
+        Ok(String::from_utf8(inscription.remark()).unwrap())
+    }
+
+    #[test]
+    fn builtin() {
+        assert_eq!(
+            remark(&["--op", "mint", "--tick", "sama", "--amt", "1000"]).unwrap(),
+            r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1000"}"#
+        );
+        assert_eq!(
+            remark(&["--protocol", "text", "--remark", "gm"]).unwrap(),
+            "gm"
+        );
+        let err = remark(&["--protocol", "brc-20", "--remark", "{}"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown protocol brc-20, expected one of binary, dot-20, json, ksm-20, text"
+        );
+    }
+
+    #[test]
+    fn dot20_mint_without_amt() {
+        let mint = r#"{"p":"dot-20","op":"mint","tick":"DOTA"}"#;
+        assert_eq!(
+            remark(&["--protocol", "dot-20", "--remark", mint]).unwrap(),
+            mint
+        );
+        assert_eq!(
+            remark(&["--protocol", "dot-20", "--op", "mint", "--tick", "DOTA"]).unwrap(),
+            mint
+        );
+        let mint = r#"{"p":"ksm-20","op":"mint","tick":"sama"}"#;
+        assert!(remark(&["--remark", mint]).is_err());
+        assert!(remark(&["--op", "mint", "--tick", "sama"]).is_err());
+    }
+
+    #[test]
+    fn register_twice() {
+        let mut registry = Registry::builtin();
+        let builder: Builder = Box::new(|args| Ok(Box::new(raw::Text::from_args(args)?)));
+        assert!(registry.register(token::KSM20, builder).is_err());
+        let builder: Builder = Box::new(|args| Ok(Box::new(raw::Text::from_args(args)?)));
+        registry.register("gm", builder).unwrap();
+        let inscription = registry
+            .build(&args(&["--protocol", "gm", "--remark", "gm"]))
+            .unwrap();
+        assert_eq!(inscription.remark(), b"gm");
+    }
+}
@@ -0,0 +1,96 @@
+//! The protocols that inscribe `--remark` as given.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use super::{InscriptionArgs, InscriptionProtocol};
+
+pub const JSON: &str = "json";
+pub const TEXT: &str = "text";
+pub const BINARY: &str = "binary";
+
+/// An ordinals-style JSON object naming its protocol in `p`, e.g. `{"p":"sns","op":"reg",...}`.
+///
+/// Unlike the token protocols, the fields are not known, so the remark is kept as written.
+pub struct Json(Vec<u8>);
+
+impl Json {
+    pub fn from_args(args: &InscriptionArgs) -> Result<Self> {
+        Ok(Json(args.raw_remark()?.trim().as_bytes().to_vec()))
+    }
+}
+
+impl InscriptionProtocol for Json {
+    fn name(&self) -> &str {
+        JSON
+    }
+
+    fn remark(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+
+    fn validate(&self, remark: &[u8]) -> Result<()> {
+        let object: Map<String, Value> =
+            serde_json::from_slice(remark).context("Invalid JSON remark")?;
+        match object.get("p") {
+            Some(Value::String(p)) if !p.is_empty() => Ok(()),
+            _ => bail!("The JSON remark must name its protocol in \"p\""),
+        }
+    }
+}
+
+/// Plain UTF-8 text.
+pub struct Text(String);
+
+impl Text {
+    pub fn from_args(args: &InscriptionArgs) -> Result<Self> {
+        Ok(Text(args.raw_remark()?.to_string()))
+    }
+}
+
+impl InscriptionProtocol for Text {
+    fn name(&self) -> &str {
+        TEXT
+    }
+
+    fn remark(&self) -> Vec<u8> {
+        self.0.as_bytes().to_vec()
+    }
+
+    fn validate(&self, remark: &[u8]) -> Result<()> {
+        let text = std::str::from_utf8(remark).context("The text remark must be UTF-8")?;
+        if text.is_empty() {
+            bail!("The text remark is empty");
+        }
+        Ok(())
+    }
+}
+
+/// Arbitrary bytes, given as hex with an optional `0x` prefix.
+pub struct Binary(Vec<u8>);
+
+impl Binary {
+    pub fn from_args(args: &InscriptionArgs) -> Result<Self> {
+        let remark = args.raw_remark()?;
+        let bytes = hex::decode(remark.strip_prefix("0x").unwrap_or(remark))
+            .context("The binary remark must be hex")?;
+        Ok(Binary(bytes))
+    }
+}
+
+impl InscriptionProtocol for Binary {
+    fn name(&self) -> &str {
+        BINARY
+    }
+
+    fn remark(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+
+    fn validate(&self, remark: &[u8]) -> Result<()> {
+        if remark.is_empty() {
+            bail!("The binary remark is empty");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,394 @@
+//! The fungible token protocols, ksm-20 and dot-20.
+//!
+//! An inscription is a JSON object, e.g. `{"p":"ksm-20","op":"mint","tick":"sama","amt":"1000"}`.
+//! The amounts are decimal strings. The protocols differ in the `p` field and in that the dot-20
+//! mints may leave out `amt`, e.g. `{"p":"dot-20","op":"mint","tick":"DOTA"}`, to mint whatever the
+//! deploy allows per mint.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use subxt::utils::AccountId32;
+
+use super::{InscriptionArgs, InscriptionProtocol};
+
+pub const KSM20: &str = "ksm-20";
+pub const DOT20: &str = "dot-20";
+
+/// The longest tick accepted.
+const MAX_TICK_LEN: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Creates a token with the given max supply and the limit per mint.
+    Deploy { tick: String, max: u128, lim: u128 },
+    /// `amt` is only optional for dot-20.
+    Mint { tick: String, amt: Option<u128> },
+    /// Moves the tokens of the sender to the SS58 address `to`.
+    Transfer { tick: String, amt: u128, to: String },
+}
+
+/// An operation inscribed under one of the token protocols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    protocol: &'static str,
+    op: Op,
+}
+
+/// The JSON representation. The order of the fields is the canonical one.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Json {
+    p: String,
+    op: String,
+    tick: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lim: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+}
+
+impl Token {
+    /// Parses and validates a remark of the given protocol.
+    pub fn parse(protocol: &'static str, remark: &[u8]) -> Result<Self> {
+        let json: Json = serde_json::from_slice(remark)
+            .with_context(|| format!("Invalid {} remark", protocol))?;
+        if json.p != protocol {
+            bail!("Expected protocol {}, got {}", protocol, json.p);
+        }
+        let take = |name: &str, value: Option<String>| {
+            value.ok_or_else(|| anyhow!("{} requires {}", json.op, name))
+        };
+        let reject = |name: &str, value: &Option<String>| match value {
+            Some(_) => Err(anyhow!("{} doesn't take {}", json.op, name)),
+            None => Ok(()),
+        };
+        let op = match json.op.as_str() {
+            "deploy" => {
+                reject("amt", &json.amt)?;
+                reject("to", &json.to)?;
+                Op::Deploy {
+                    tick: json.tick.clone(),
+                    max: parse_amount(&take("max", json.max.clone())?)?,
+                    lim: parse_amount(&take("lim", json.lim.clone())?)?,
+                }
+            }
+            "mint" => {
+                reject("max", &json.max)?;
+                reject("lim", &json.lim)?;
+                reject("to", &json.to)?;
+                let amt = match json.amt.clone() {
+                    None if protocol == DOT20 => None,
+                    amt => Some(parse_amount(&take("amt", amt)?)?),
+                };
+                Op::Mint {
+                    tick: json.tick.clone(),
+                    amt,
+                }
+            }
+            "transfer" => {
+                reject("max", &json.max)?;
+                reject("lim", &json.lim)?;
+                Op::Transfer {
+                    tick: json.tick.clone(),
+                    amt: parse_amount(&take("amt", json.amt.clone())?)?,
+                    to: take("to", json.to.clone())?,
+                }
+            }
+            op => bail!("Unknown {} operation {}", protocol, op),
+        };
+        let token = Token { protocol, op };
+        token.check()?;
+        Ok(token)
+    }
+
+    /// Builds the inscription from `--remark` or from `--op` and its fields.
+    pub fn from_args(protocol: &'static str, args: &InscriptionArgs) -> Result<Self> {
+        if let Some(remark) = &args.remark {
+            return Token::parse(protocol, remark.as_bytes());
+        }
+        let Some(op) = args.op else {
+            bail!("Either --remark or --op is required");
+        };
+        let tick = args.tick.clone().context("--tick is required")?;
+        let amount = |name: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .ok_or_else(|| anyhow!("--{} is required", name))
+                .and_then(parse_amount)
+        };
+        let op = match op {
+            OpKind::Deploy => Op::Deploy {
+                tick,
+                max: amount("max", &args.max)?,
+                lim: amount("lim", &args.lim)?,
+            },
+            OpKind::Mint => Op::Mint {
+                tick,
+                amt: match &args.amt {
+                    None if protocol == DOT20 => None,
+                    amt => Some(amount("amt", amt)?),
+                },
+            },
+            OpKind::Transfer => Op::Transfer {
+                tick,
+                amt: amount("amt", &args.amt)?,
+                to: args.to.clone().context("--to is required")?,
+            },
+        };
+        let token = Token { protocol, op };
+        token.check()?;
+        Ok(token)
+    }
+
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    pub fn tick(&self) -> &str {
+        match &self.op {
+            Op::Deploy { tick, .. } | Op::Mint { tick, .. } | Op::Transfer { tick, .. } => tick,
+        }
+    }
+
+    /// Checks the constraints that don't depend on the state of the token.
+    fn check(&self) -> Result<()> {
+        let tick = self.tick();
+        if tick.is_empty()
+            || tick.len() > MAX_TICK_LEN
+            || !tick.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            bail!(
+                "Invalid tick {:?}, expected up to {} letters and digits",
+                tick,
+                MAX_TICK_LEN
+            );
+        }
+        match &self.op {
+            Op::Deploy { max, lim, .. } => {
+                if *max == 0 || *lim == 0 || lim > max {
+                    bail!("Invalid deploy: lim must be within 1..=max");
+                }
+            }
+            Op::Mint { amt, .. } => {
+                if *amt == Some(0) {
+                    bail!("Invalid mint: amt must not be zero");
+                }
+            }
+            Op::Transfer { amt, to, .. } => {
+                if *amt == 0 {
+                    bail!("Invalid transfer: amt must not be zero");
+                }
+                AccountId32::from_str(to)
+                    .map_err(|e| anyhow!("Invalid transfer destination {}: {:?}", to, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InscriptionProtocol for Token {
+    fn name(&self) -> &str {
+        self.protocol
+    }
+
+    /// Returns the canonical JSON of the inscription.
+    fn remark(&self) -> Vec<u8> {
+        let mut json = Json {
+            p: self.protocol.to_string(),
+            op: String::new(),
+            tick: self.tick().to_string(),
+            max: None,
+            lim: None,
+            amt: None,
+            to: None,
+        };
+        match &self.op {
+            Op::Deploy { max, lim, .. } => {
+                json.op = "deploy".to_string();
+                json.max = Some(max.to_string());
+                json.lim = Some(lim.to_string());
+            }
+            Op::Mint { amt, .. } => {
+                json.op = "mint".to_string();
+                json.amt = amt.map(|amt| amt.to_string());
+            }
+            Op::Transfer { amt, to, .. } => {
+                json.op = "transfer".to_string();
+                json.amt = Some(amt.to_string());
+                json.to = Some(to.clone());
+            }
+        }
+        serde_json::to_vec(&json).expect("the JSON is always serializable")
+    }
+
+    fn validate(&self, remark: &[u8]) -> Result<()> {
+        Token::parse(self.protocol, remark).map(|_| ())
+    }
+
+    /// Deploying or transferring over and over again makes no sense, only the mints are repeated.
+    fn is_repeatable(&self) -> bool {
+        matches!(self.op, Op::Mint { .. })
+    }
+
+    fn minted_tick(&self) -> Option<&str> {
+        match &self.op {
+            Op::Mint { tick, .. } => Some(tick),
+            _ => None,
+        }
+    }
+}
+
+/// The amounts are positive integers written as decimal strings without leading zeros.
+pub(super) fn parse_amount(amount: &str) -> Result<u128> {
+    if amount.is_empty()
+        || !amount.chars().all(|c| c.is_ascii_digit())
+        || amount.len() > 1 && amount.starts_with('0')
+    {
+        bail!("Invalid amount {:?}, expected an integer", amount);
+    }
+    amount
+        .parse()
+        .map_err(|_| anyhow!("Amount {} is too large", amount))
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum OpKind {
+    Deploy,
+    Mint,
+    Transfer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+
+    fn parse(remark: &str) -> Result<Token> {
+        Token::parse(KSM20, remark.as_bytes())
+    }
+
+    fn canonical(remark: &str) -> String {
+        String::from_utf8(parse(remark).unwrap().remark()).unwrap()
+    }
+
+    #[test]
+    fn amounts() {
+        assert_eq!(parse_amount("1000").unwrap(), 1000);
+        assert_eq!(parse_amount("0").unwrap(), 0);
+        assert_eq!(
+            parse_amount("340282366920938463463374607431768211455").unwrap(),
+            u128::MAX
+        );
+        for invalid in [
+            "",
+            "01",
+            "00",
+            "-1",
+            "+1",
+            "1.0",
+            "1e3",
+            " 1",
+            "340282366920938463463374607431768211456",
+        ] {
+            assert!(parse_amount(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn amt_must_be_a_string() {
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1000"}"#).is_ok());
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":1000}"#).is_err());
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"0"}"#).is_err());
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"010"}"#).is_err());
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert!(
+            parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1","memo":"gm"}"#).is_err()
+        );
+        assert!(parse(r#"{"p":"ksm-20","op":"burn","tick":"sama","amt":"1"}"#).is_err());
+        assert!(parse(r#"{"p":"dot-20","op":"mint","tick":"sama","amt":"1"}"#).is_err());
+    }
+
+    #[test]
+    fn fields_of_other_ops() {
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1","max":"10"}"#).is_err());
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1","lim":"1"}"#).is_err());
+        let to = format!(
+            r#"{{"p":"ksm-20","op":"mint","tick":"sama","amt":"1","to":"{}"}}"#,
+            ALICE
+        );
+        assert!(parse(&to).is_err());
+        assert!(parse(
+            r#"{"p":"ksm-20","op":"deploy","tick":"sama","max":"10","lim":"1","amt":"1"}"#
+        )
+        .is_err());
+        let lim = format!(
+            r#"{{"p":"ksm-20","op":"transfer","tick":"sama","amt":"1","to":"{}","lim":"1"}}"#,
+            ALICE
+        );
+        assert!(parse(&lim).is_err());
+    }
+
+    #[test]
+    fn ticks_and_limits() {
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"","amt":"1"}"#).is_err());
+        assert!(parse(r#"{"p":"ksm-20","op":"mint","tick":"sa ma","amt":"1"}"#).is_err());
+        assert!(
+            parse(r#"{"p":"ksm-20","op":"mint","tick":"abcdefghijklmnopq","amt":"1"}"#).is_err()
+        );
+        assert!(
+            parse(r#"{"p":"ksm-20","op":"deploy","tick":"sama","max":"10","lim":"11"}"#).is_err()
+        );
+        assert!(
+            parse(r#"{"p":"ksm-20","op":"deploy","tick":"sama","max":"0","lim":"0"}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn transfer_destination() {
+        let transfer = |to: &str| {
+            parse(&format!(
+                r#"{{"p":"ksm-20","op":"transfer","tick":"sama","amt":"1","to":"{}"}}"#,
+                to
+            ))
+        };
+        assert!(transfer(ALICE).is_ok());
+        assert!(transfer("alice").is_err());
+        // The checksum is off.
+        assert!(transfer("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQZ").is_err());
+        assert!(transfer("").is_err());
+    }
+
+    #[test]
+    fn canonical_remarks() {
+        assert_eq!(
+            canonical(
+                r#"{"lim":"1000","max":"21000000","tick":"sama","op":"deploy","p":"ksm-20"}"#
+            ),
+            r#"{"p":"ksm-20","op":"deploy","tick":"sama","max":"21000000","lim":"1000"}"#
+        );
+        assert_eq!(
+            canonical(r#"{ "amt": "1000", "tick": "sama", "op": "mint", "p": "ksm-20" }"#),
+            r#"{"p":"ksm-20","op":"mint","tick":"sama","amt":"1000"}"#
+        );
+        assert_eq!(
+            canonical(&format!(
+                r#"{{"to":"{}","amt":"5","tick":"sama","op":"transfer","p":"ksm-20"}}"#,
+                ALICE
+            )),
+            format!(
+                r#"{{"p":"ksm-20","op":"transfer","tick":"sama","amt":"5","to":"{}"}}"#,
+                ALICE
+            )
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Obtaining the root keypair.
+
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use subxt::{
+    tx::Signer,
+    utils::{AccountId32, MultiSignature},
+};
+use subxt_signer::{
+    ecdsa,
+    sr25519::{self, Seed},
+    DeriveJunction, SecretUri,
+};
+use zeroize::Zeroizing;
+
+use crate::{keystore::Keystore, metadata, polkadot_js};
+
+/// The signature schemes the root account could use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    #[default]
+    Sr25519,
+    Ed25519,
+    Ecdsa,
+}
+
+/// A keypair of any of the supported schemes.
+///
+/// Usually an sr25519 keypair is known by its seed. However, polkadot-js doesn't keep the seeds of
+/// the accounts it stores, only the expanded secret keys, so the latter are supported as well.
+#[derive(Clone)]
+pub enum Keypair {
+    Seed(sr25519::Keypair),
+    Expanded(schnorrkel::Keypair),
+    Ed25519(ed25519_zebra::SigningKey),
+    Ecdsa(ecdsa::Keypair),
+}
+
+impl Keypair {
+    pub fn account_id(&self) -> AccountId32 {
+        match self {
+            Keypair::Seed(keypair) => keypair.public_key().into(),
+            Keypair::Expanded(keypair) => AccountId32(keypair.public.to_bytes()),
+            Keypair::Ed25519(keypair) => {
+                AccountId32(ed25519_zebra::VerificationKey::from(keypair).into())
+            }
+            // The account of an ecdsa key is the hash of its public key.
+            Keypair::Ecdsa(keypair) => {
+                <ecdsa::Keypair as Signer<metadata::Config>>::account_id(keypair)
+            }
+        }
+    }
+
+    /// Returns the raw public key. Unlike the account ID, it's 33 bytes long for ecdsa.
+    pub fn public_key(&self) -> Vec<u8> {
+        match self {
+            Keypair::Seed(keypair) => keypair.public_key().0.to_vec(),
+            Keypair::Expanded(keypair) => keypair.public.to_bytes().to_vec(),
+            Keypair::Ed25519(keypair) => ed25519_zebra::VerificationKey::from(keypair)
+                .as_ref()
+                .to_vec(),
+            Keypair::Ecdsa(keypair) => keypair.public_key().0.to_vec(),
+        }
+    }
+
+    /// Derives a new keypair the same way substrate does.
+    ///
+    /// Only sr25519 supports soft derivation, the other schemes fail on soft junctions.
+    pub fn derive(&self, junctions: impl IntoIterator<Item = DeriveJunction>) -> Result<Keypair> {
+        match self {
+            Keypair::Seed(keypair) => Ok(Keypair::Seed(keypair.derive(junctions))),
+            Keypair::Expanded(keypair) => {
+                use schnorrkel::derive::{ChainCode, Derivation};
+
+                let mut junctions = junctions.into_iter();
+                let mut keypair = keypair.clone();
+                while let Some(junction) = junctions.next() {
+                    match junction {
+                        DeriveJunction::Soft(cc) => {
+                            keypair = keypair.derived_key_simple(ChainCode(cc), b"").0;
+                        }
+                        DeriveJunction::Hard(cc) => {
+                            // This yields a mini secret key, i.e. a seed. From now on, everything
+                            // could be handled by `subxt_signer`.
+                            let (seed, _) = keypair
+                                .secret
+                                .hard_derive_mini_secret_key(Some(ChainCode(cc)), b"");
+                            let keypair = sr25519::Keypair::from_seed(seed.to_bytes())
+                                .expect("mini secret key is a valid seed");
+                            return Keypair::Seed(keypair).derive(junctions);
+                        }
+                    }
+                }
+                Ok(Keypair::Expanded(keypair))
+            }
+            Keypair::Ed25519(keypair) => {
+                let mut seed = Zeroizing::new(<[u8; 32]>::from(*keypair));
+                for junction in junctions {
+                    match junction {
+                        DeriveJunction::Soft(_) => bail!("ed25519 doesn't support soft derivation"),
+                        DeriveJunction::Hard(cc) => *seed = ed25519_hard_derive(&seed, &cc),
+                    }
+                }
+                Ok(Keypair::Ed25519(ed25519_zebra::SigningKey::from(*seed)))
+            }
+            Keypair::Ecdsa(keypair) => {
+                Ok(Keypair::Ecdsa(keypair.derive(junctions).map_err(|e| {
+                    anyhow!("Failed to derive the ecdsa key: {}", e)
+                })?))
+            }
+        }
+    }
+}
+
+/// The hard derivation of substrate's ed25519, i.e. the blake2-256 hash of the SCALE-encoded
+/// `("Ed25519HDKD", seed, chain_code)`.
+fn ed25519_hard_derive(seed: &[u8; 32], cc: &[u8; 32]) -> [u8; 32] {
+    use blake2::{digest::consts::U32, Blake2b, Digest};
+
+    const ID: &[u8] = b"Ed25519HDKD";
+    Blake2b::<U32>::new()
+        .chain_update([(ID.len() as u8) << 2]) // the compact-encoded length
+        .chain_update(ID)
+        .chain_update(seed)
+        .chain_update(cc)
+        .finalize()
+        .into()
+}
+
+impl Signer<metadata::Config> for Keypair {
+    fn account_id(&self) -> AccountId32 {
+        Keypair::account_id(self)
+    }
+
+    fn address(&self) -> <metadata::Config as subxt::Config>::Address {
+        Keypair::account_id(self).into()
+    }
+
+    fn sign(&self, signer_payload: &[u8]) -> MultiSignature {
+        match self {
+            Keypair::Seed(keypair) => {
+                <sr25519::Keypair as Signer<metadata::Config>>::sign(keypair, signer_payload)
+            }
+            Keypair::Expanded(keypair) => {
+                let context = schnorrkel::signing_context(b"substrate");
+                MultiSignature::Sr25519(keypair.sign(context.bytes(signer_payload)).to_bytes())
+            }
+            Keypair::Ed25519(keypair) => {
+                MultiSignature::Ed25519(keypair.sign(signer_payload).to_bytes())
+            }
+            Keypair::Ecdsa(keypair) => {
+                <ecdsa::Keypair as Signer<metadata::Config>>::sign(keypair, signer_payload)
+            }
+        }
+    }
+}
+
+/// The ways to specify the root account. Exactly one of them must be given.
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct RootKey {
+    /// The hex-encoded 32-byte seed of the root account.
+    #[clap(long)]
+    private_key: Option<String>,
+    /// The secret URI of the root account, e.g. `<mnemonic>//hard/soft///password`.
+    ///
+    /// The phrase could be either a BIP39 mnemonic or a hex-encoded seed. Produces the same account
+    /// as polkadot-js or subkey would.
+    #[clap(long)]
+    suri: Option<String>,
+    /// Read the hex seed or the secret URI of the root account from the given file.
+    #[clap(long)]
+    private_key_file: Option<PathBuf>,
+    /// Read the hex seed or the secret URI of the root account from the given environment
+    /// variable.
+    #[clap(long, value_name = "VAR")]
+    private_key_env: Option<String>,
+    /// Prompt for the hex seed or the secret URI of the root account.
+    #[clap(long)]
+    private_key_prompt: bool,
+    /// Import the root account from a polkadot-js JSON export. The password is prompted for.
+    #[clap(long, value_name = "FILE")]
+    keystore: Option<PathBuf>,
+    /// Use the root account stored in the local keystore under the given name. See the `key`
+    /// subcommand. The passphrase is prompted for.
+    #[clap(long, value_name = "NAME")]
+    key: Option<String>,
+}
+
+impl RootKey {
+    /// Returns the root keypair of the given scheme. The keystores know the scheme of their keys,
+    /// so it's only checked for them.
+    pub fn keypair(&self, scheme: Scheme) -> Result<Keypair> {
+        if let Some(private_key) = &self.private_key {
+            return from_hex_seed(private_key, scheme);
+        }
+        if let Some(suri) = &self.suri {
+            return from_suri(suri, scheme);
+        }
+        if let Some(path) = &self.private_key_file {
+            let secret = Zeroizing::new(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+            );
+            return from_secret(&secret, scheme);
+        }
+        if let Some(var) = &self.private_key_env {
+            let secret = Zeroizing::new(
+                std::env::var(var).with_context(|| format!("Failed to read ${}", var))?,
+            );
+            return from_secret(&secret, scheme);
+        }
+        if self.private_key_prompt {
+            let secret = Zeroizing::new(rpassword::prompt_password("Root key: ")?);
+            return from_secret(&secret, scheme);
+        }
+        if let Some(path) = &self.keystore {
+            if scheme != Scheme::Sr25519 {
+                bail!("Only sr25519 polkadot-js exports are supported");
+            }
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let password = Zeroizing::new(rpassword::prompt_password("Keystore password: ")?);
+            return polkadot_js::decrypt(&json, &password);
+        }
+        if let Some(name) = &self.key {
+            let entry = Keystore::open().load(name)?;
+            if entry.scheme() != scheme {
+                bail!("Key {} is {:?}, not {:?}", name, entry.scheme(), scheme);
+            }
+            let passphrase = Zeroizing::new(rpassword::prompt_password("Passphrase: ")?);
+            return entry.unlock(&passphrase);
+        }
+        bail!("The root account must be specified")
+    }
+}
+
+/// The secrets that are not passed directly could be either hex seeds or secret URIs.
+pub fn from_secret(secret: &str, scheme: Scheme) -> Result<Keypair> {
+    let secret = secret.trim();
+    let hex = secret.strip_prefix("0x").unwrap_or(secret);
+    if hex.len() == 2 * std::mem::size_of::<Seed>() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        from_hex_seed(secret, scheme)
+    } else {
+        from_suri(secret, scheme)
+    }
+}
+
+fn from_hex_seed(private_key: &str, scheme: Scheme) -> Result<Keypair> {
+    // strip 0x prefix
+    let private_key = private_key.strip_prefix("0x").unwrap_or(private_key);
+    let raw = Zeroizing::new(hex::decode(private_key).map_err(|e| anyhow!(e))?);
+    let mut seed = Zeroizing::new(Seed::default());
+    if raw.len() != seed.len() {
+        bail!(
+            "Keyfile length invalid, expected {} bytes, got {} bytes",
+            seed.len(),
+            raw.len()
+        );
+    }
+    seed.copy_from_slice(&raw[..]);
+    match scheme {
+        Scheme::Sr25519 => Ok(Keypair::Seed(sr25519::Keypair::from_seed(*seed)?)),
+        Scheme::Ed25519 => Ok(Keypair::Ed25519(ed25519_zebra::SigningKey::from(*seed))),
+        Scheme::Ecdsa => Ok(Keypair::Ecdsa(
+            ecdsa::Keypair::from_seed(*seed).map_err(|e| anyhow!("Invalid ecdsa seed: {}", e))?,
+        )),
+    }
+}
+
+fn from_suri(suri: &str, scheme: Scheme) -> Result<Keypair> {
+    let uri = SecretUri::from_str(suri).map_err(|e| anyhow!("Invalid secret URI: {}", e))?;
+    match scheme {
+        Scheme::Sr25519 => {
+            let keypair = sr25519::Keypair::from_uri(&uri)
+                .map_err(|e| anyhow!("Invalid secret URI: {}", e))?;
+            Ok(Keypair::Seed(keypair))
+        }
+        Scheme::Ed25519 => ed25519_from_uri(&uri),
+        Scheme::Ecdsa => {
+            let keypair =
+                ecdsa::Keypair::from_uri(&uri).map_err(|e| anyhow!("Invalid secret URI: {}", e))?;
+            Ok(Keypair::Ecdsa(keypair))
+        }
+    }
+}
+
+/// `subxt_signer` doesn't support ed25519, so the secret URI is resolved the same way substrate
+/// does: the phrase is either a hex seed or a BIP39 mnemonic, followed by hard junctions.
+fn ed25519_from_uri(uri: &SecretUri) -> Result<Keypair> {
+    let phrase = uri.phrase.expose_secret();
+    let mut seed = Zeroizing::new([0u8; 32]);
+    if let Some(hex) = phrase.strip_prefix("0x") {
+        let raw = Zeroizing::new(hex::decode(hex).map_err(|e| anyhow!(e))?);
+        if raw.len() != seed.len() {
+            bail!("Invalid seed length in the secret URI");
+        }
+        seed.copy_from_slice(&raw[..]);
+    } else {
+        let mnemonic = bip39::Mnemonic::parse(phrase.as_str())
+            .map_err(|e| anyhow!("Invalid secret URI: {}", e))?;
+        let entropy = Zeroizing::new(mnemonic.to_entropy());
+        let password = uri
+            .password
+            .as_ref()
+            .map_or("", |p| p.expose_secret().as_str());
+        // The mini secret key of substrate-bip39.
+        let salt = Zeroizing::new(format!("mnemonic{}", password));
+        let mut bip39_seed = Zeroizing::new([0u8; 64]);
+        pbkdf2::pbkdf2_hmac::<sha2::Sha512>(&entropy, salt.as_bytes(), 2048, &mut bip39_seed[..]);
+        seed.copy_from_slice(&bip39_seed[..32]);
+    }
+    Keypair::Ed25519(ed25519_zebra::SigningKey::from(*seed)).derive(uri.junctions.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHRASE: &str = "bottom drive obey lake curtain smoke basket hold race lonely fit walk";
+
+    fn public_key(suri: &str, scheme: Scheme) -> String {
+        hex::encode(from_suri(suri, scheme).unwrap().public_key())
+    }
+
+    fn ed25519_seed(suri: &str) -> [u8; 32] {
+        match from_suri(suri, Scheme::Ed25519).unwrap() {
+            Keypair::Ed25519(keypair) => keypair.into(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn dev_accounts() {
+        // As printed by `subkey inspect --scheme <scheme> //Alice`, and `//Bob`.
+        assert_eq!(
+            public_key("//Alice", Scheme::Sr25519),
+            "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"
+        );
+        assert_eq!(
+            public_key("//Alice", Scheme::Ed25519),
+            "88dc3417d5058ec4b4503e0c12ea1a0a89be200fe98922423d4334014fa6b0ee"
+        );
+        assert_eq!(
+            public_key("//Bob", Scheme::Ed25519),
+            "d17c2d7823ebf260fd138f2d7e27d114c0145d968b5ff5006125f2414fadae69"
+        );
+        assert_eq!(
+            public_key("//Alice", Scheme::Ecdsa),
+            "020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1"
+        );
+        assert_eq!(
+            public_key(&format!("{}//Alice", PHRASE), Scheme::Ed25519),
+            public_key("//Alice", Scheme::Ed25519)
+        );
+    }
+
+    #[test]
+    fn ed25519_password() {
+        // The mini secret of a mnemonic doesn't depend on the scheme, so it's checked against the
+        // one of ecdsa, which `subxt_signer` tests against substrate.
+        let suri = format!("{}///password", PHRASE);
+        let seed = ed25519_seed(&suri);
+        assert_eq!(
+            hex::encode(ecdsa::Keypair::from_seed(seed).unwrap().public_key().0),
+            public_key(&suri, Scheme::Ecdsa)
+        );
+        assert_ne!(seed, ed25519_seed(PHRASE));
+
+        let hard = DeriveJunction::hard("hard");
+        assert_eq!(
+            ed25519_seed(&format!("{}//hard///password", PHRASE)),
+            ed25519_hard_derive(&seed, hard.inner())
+        );
+    }
+
+    #[test]
+    fn ed25519_soft_derivation() {
+        assert!(from_suri("//Alice/soft", Scheme::Ed25519).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! The local keystore for the root keys.
+//!
+//! Every key is stored in `keystore/<name>.json` next to the database. The secret URI of the key is
+//! encrypted with a passphrase the same way polkadot-js encrypts its exports.
+
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    key::{self, Keypair, Scheme},
+    metadata::{self, Chain},
+    polkadot_js,
+};
+
+const KEYSTORE_DIR: &str = "keystore";
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum KeyCommand {
+    /// Generate a new random root key. Its secret phrase is printed once.
+    Generate { name: String },
+    /// Import an existing root key. The hex seed or the secret URI is prompted for.
+    Import { name: String },
+    /// List the stored root keys.
+    List,
+    /// Print the public key and the address of a stored root key.
+    ExportPublic { name: String },
+}
+
+impl KeyCommand {
+    /// Runs the command. The addresses are formatted for the given chain, if any. The new keys
+    /// are of the given scheme.
+    pub fn run(self, chain: Option<Chain>, scheme: Scheme) -> Result<()> {
+        let keystore = Keystore::open();
+        let ss58_prefix =
+            chain.map_or(metadata::SUBSTRATE_SS58_PREFIX, |chain| chain.ss58_prefix());
+        match self {
+            KeyCommand::Generate { name } => {
+                use rand::RngCore;
+                let mut entropy = Zeroizing::new([0u8; 32]);
+                rand::rngs::OsRng.fill_bytes(&mut entropy[..]);
+                let mnemonic = bip39::Mnemonic::from_entropy(&entropy[..])?;
+                let secret = Zeroizing::new(mnemonic.to_string());
+                let keypair = keystore.insert(&name, &secret, scheme)?;
+                println!(
+                    "generated {}: {}",
+                    name,
+                    metadata::to_ss58(ss58_prefix, &keypair.account_id())
+                );
+                // The only way to get the key out of the keystore, e.g. into a wallet.
+                println!("secret phrase, write it down as it's not shown again:");
+                println!("{}", *secret);
+            }
+            KeyCommand::Import { name } => {
+                let secret = Zeroizing::new(rpassword::prompt_password("Root key: ")?);
+                let keypair = keystore.insert(&name, secret.trim(), scheme)?;
+                println!(
+                    "imported {}: {}",
+                    name,
+                    metadata::to_ss58(ss58_prefix, &keypair.account_id())
+                );
+            }
+            KeyCommand::List => {
+                for (name, entry) in keystore.list()? {
+                    println!(
+                        "{} ({:?}): {}",
+                        name,
+                        entry.scheme,
+                        metadata::to_ss58(ss58_prefix, &entry.account_id()?)
+                    );
+                }
+            }
+            KeyCommand::ExportPublic { name } => {
+                let entry = keystore.load(&name)?;
+                println!("scheme: {:?}", entry.scheme);
+                println!("account id: {}", entry.public);
+                println!(
+                    "address: {}",
+                    metadata::to_ss58(ss58_prefix, &entry.account_id()?)
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Keystore {
+    dir: PathBuf,
+}
+
+impl Keystore {
+    pub fn open() -> Self {
+        Self {
+            dir: PathBuf::from(KEYSTORE_DIR),
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!(
+                "Invalid key name {:?}, use letters, digits, '-' and '_'",
+                name
+            );
+        }
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+
+    /// Encrypts and stores the given secret (a hex seed or a secret URI) under the given name.
+    fn insert(&self, name: &str, secret: &str, scheme: Scheme) -> Result<Keypair> {
+        let path = self.path(name)?;
+        if path.exists() {
+            bail!("Key {} already exists", name);
+        }
+        let keypair = key::from_secret(secret, scheme)?;
+        let passphrase = Zeroizing::new(rpassword::prompt_password("Passphrase: ")?);
+        let confirmation = Zeroizing::new(rpassword::prompt_password("Repeat passphrase: ")?);
+        if passphrase != confirmation {
+            bail!("Passphrases do not match");
+        }
+        let encoded = polkadot_js::encrypt_encoded(secret.as_bytes(), &passphrase)?;
+        let entry = Entry {
+            scheme,
+            public: format!("0x{}", hex::encode(keypair.account_id().0)),
+            encoded: base64::engine::general_purpose::STANDARD.encode(encoded),
+        };
+        std::fs::create_dir_all(&self.dir)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // The secret is encrypted, but there is no reason to let anyone else read it either.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let json = serde_json::to_string_pretty(&entry)?;
+        options
+            .open(&path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(keypair)
+    }
+
+    pub fn load(&self, name: &str) -> Result<Entry> {
+        let path = self.path(name)?;
+        let json = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Returns the stored keys sorted by name.
+    fn list(&self) -> Result<Vec<(String, Entry)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in std::fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            entries.push((name.to_string(), self.load(name)?));
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// The keys stored before other schemes were supported are sr25519.
+    #[serde(default)]
+    scheme: Scheme,
+    /// The hex-encoded account ID. Except for ecdsa, it's the public key.
+    public: String,
+    /// The base64-encoded encrypted secret, see [`polkadot_js::encrypt_encoded`].
+    encoded: String,
+}
+
+impl Entry {
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    fn account_id(&self) -> Result<subxt::utils::AccountId32> {
+        let public = hex::decode(self.public.trim_start_matches("0x"))?;
+        let public: [u8; 32] = public
+            .try_into()
+            .map_err(|_| anyhow!("Invalid public key {}", self.public))?;
+        Ok(subxt::utils::AccountId32(public))
+    }
+
+    /// Decrypts the keypair with the given passphrase.
+    pub fn unlock(&self, passphrase: &str) -> Result<Keypair> {
+        let encoded = base64::engine::general_purpose::STANDARD.decode(&self.encoded)?;
+        let secret = polkadot_js::decrypt_encoded(&encoded, passphrase)?;
+        let secret = std::str::from_utf8(&secret)?;
+        let keypair = key::from_secret(secret, self.scheme)?;
+        if keypair.account_id() != self.account_id()? {
+            bail!("The stored key doesn't match its public key");
+        }
+        Ok(keypair)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+    const ALICE: &str = "0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+    const BOB: &str = "0x8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48";
+
+    /// `//Alice` as stored before the scheme was recorded. Once written, the files are never
+    /// rewritten, so this has to keep unlocking.
+    fn alice() -> Entry {
+        serde_json::from_str(&format!(
+            r#"{{
+                "public": "{}",
+                "encoded": "enMzmdPDE1C5rtNmeVdgQjS5RL3YUwRyEeky0nyiqxQAgAAAAQAAAAgAAABBhd5w6lrwKGO3gecor6/pGOTufGNwFVSWJm8cH6HxBI4ag92y/ZA2L5DF9R/RRw=="
+            }}"#,
+            ALICE
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn unlocks() {
+        let entry = alice();
+        assert_eq!(entry.scheme(), Scheme::Sr25519);
+        let keypair = entry.unlock(PASSPHRASE).unwrap();
+        assert_eq!(keypair.account_id(), entry.account_id().unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase() {
+        assert!(alice().unlock("wrong").is_err());
+    }
+
+    #[test]
+    fn rejects_other_public() {
+        let entry = Entry {
+            public: BOB.to_string(),
+            ..alice()
+        };
+        assert!(entry.unlock(PASSPHRASE).is_err());
+        let entry = Entry {
+            scheme: Scheme::Ed25519,
+            ..alice()
+        };
+        assert!(entry.unlock(PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn key_names() {
+        let keystore = Keystore::open();
+        assert_eq!(
+            keystore.path("root-1_a").unwrap(),
+            PathBuf::from("keystore/root-1_a.json")
+        );
+        for name in ["", "../root", "root.json", "root key", "клуч"] {
+            assert!(keystore.path(name).is_err(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! The limits of a run: how many mints, how much spent and until when.
+//!
+//! The limits are checked before every hop, so a run stops between the hops rather than in the
+//! middle of one.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{db::Database, metadata::Chain, Rpc};
+
+/// When to stop.
+#[derive(Clone, Copy, Debug)]
+pub enum Until {
+    /// The Unix timestamp in seconds.
+    Time(u64),
+    /// The number of the best block.
+    Block(u32),
+}
+
+impl Until {
+    /// Parses either `#BLOCK`, a Unix timestamp in seconds or a UTC time `YYYY-MM-DDTHH:MM:SSZ`.
+    ///
+    /// The times in the past are rejected, mostly to catch the block numbers given without `#`.
+    pub fn parse(until: &str) -> Result<Self> {
+        if let Some(block) = until.strip_prefix('#') {
+            let block = block
+                .parse()
+                .map_err(|_| anyhow!("Invalid block number {}", until))?;
+            return Ok(Until::Block(block));
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if let Ok(timestamp) = until.parse::<u64>() {
+            if timestamp <= now {
+                bail!(
+                    "--until {} is in the past, use #{} to stop at a block",
+                    until,
+                    until
+                );
+            }
+            return Ok(Until::Time(timestamp));
+        }
+        let timestamp = parse_utc(until)
+            .ok_or_else(|| anyhow!("Expected #BLOCK, a Unix timestamp or YYYY-MM-DDTHH:MM:SSZ"))?;
+        if timestamp <= now {
+            bail!("--until {} is in the past", until);
+        }
+        Ok(Until::Time(timestamp))
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SSZ` into a Unix timestamp.
+fn parse_utc(time: &str) -> Option<u64> {
+    let (date, time) = time.strip_suffix('Z')?.split_once('T')?;
+    let date = date
+        .split('-')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let time = time
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [year, month, day] = <[i64; 3]>::try_from(date).ok()?;
+    let [hour, minute, second] = <[i64; 3]>::try_from(time).ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..60).contains(&second)
+    {
+        return None;
+    }
+    // The days since the epoch in the proleptic Gregorian calendar, with the years starting in
+    // March so that the leap day comes last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+pub struct Limits {
+    max_mints: Option<u64>,
+    max_spend: Option<u128>,
+    until: Option<Until>,
+    /// The ID of the last transaction signed before the run started.
+    txns_before: i64,
+}
+
+impl Limits {
+    pub fn new(
+        chain: Chain,
+        max_mints: Option<u64>,
+        max_spend: Option<&str>,
+        until: Option<&str>,
+    ) -> Result<Self> {
+        if max_mints == Some(0) {
+            bail!("--max-mints must be positive");
+        }
+        Ok(Limits {
+            max_mints,
+            max_spend: max_spend
+                .map(|max_spend| chain.parse_balance(max_spend))
+                .transpose()?,
+            until: until.map(Until::parse).transpose()?,
+            txns_before: 0,
+        })
+    }
+
+    /// Takes note of the transactions signed before the run, so that only the fees of the ones
+    /// signed by the run count. The fees of the former could still be recorded during the run, e.g.
+    /// by the watcher catching up with the blocks finalized in the meantime.
+    pub async fn begin(&mut self, db: &Database) -> Result<()> {
+        self.txns_before = db.last_txn_id().await?;
+        Ok(())
+    }
+
+    /// Returns the fees recorded for the transactions signed by the run.
+    pub async fn spent(&self, db: &Database) -> Result<u128> {
+        db.fees_since(self.txns_before).await
+    }
+
+    /// Returns how many more mints the run may submit, if limited.
+    pub fn remaining_mints(&self, minted: u64) -> Option<u64> {
+        self.max_mints
+            .map(|max_mints| max_mints.saturating_sub(minted))
+    }
+
+    /// Returns the reason to stop if any of the limits is reached, given the number of the mints
+    /// submitted by the run.
+    pub async fn reached(
+        &self,
+        chain: Chain,
+        rpc: &Rpc,
+        db: &Database,
+        minted: u64,
+    ) -> Result<Option<String>> {
+        if let Some(max_mints) = self.max_mints {
+            if minted >= max_mints {
+                return Ok(Some(format!("submitted {} mints", minted)));
+            }
+        }
+        if let Some(max_spend) = self.max_spend {
+            let spent = self.spent(db).await?;
+            if spent >= max_spend {
+                return Ok(Some(format!("spent {}", chain.format_balance(spent))));
+            }
+        }
+        match self.until {
+            Some(Until::Time(until)) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                if now >= until {
+                    return Ok(Some(format!("reached the deadline {}", until)));
+                }
+            }
+            Some(Until::Block(until)) => {
+                let best = rpc.best_block_number().await?;
+                if best >= until {
+                    return Ok(Some(format!("reached block #{}", best)));
+                }
+            }
+            None => {}
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc() {
+        assert_eq!(parse_utc("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_utc("2000-02-29T12:00:00Z"), Some(951825600));
+        assert_eq!(parse_utc("2024-02-29T00:00:00Z"), Some(1709164800));
+        assert_eq!(parse_utc("2023-12-31T23:59:59Z"), Some(1704067199));
+        assert_eq!(parse_utc("2024-01-01T00:00:00Z"), Some(1704067200));
+        assert_eq!(parse_utc("2026-10-18T12:00:00Z"), Some(1792324800));
+    }
+
+    #[test]
+    fn impossible_utc() {
+        for time in [
+            "2026-02-31T00:00:00Z",
+            "2026-02-29T00:00:00Z",
+            "1900-02-29T00:00:00Z",
+            "2026-04-31T00:00:00Z",
+            "2026-13-01T00:00:00Z",
+            "2026-00-01T00:00:00Z",
+            "2026-01-00T00:00:00Z",
+            "2026-01-01T24:00:00Z",
+            "2026-01-01T00:60:00Z",
+            "2026-01-01T00:00:00",
+            "2026-01-01 00:00:00Z",
+            "1969-12-31T23:59:59Z",
+        ] {
+            assert_eq!(parse_utc(time), None, "{}", time);
+        }
+    }
+
+    #[test]
+    fn until() {
+        assert!(matches!(
+            Until::parse("#21000000"),
+            Ok(Until::Block(21000000))
+        ));
+        assert!(Until::parse("#-1").is_err());
+        assert!(Until::parse("#").is_err());
+        // A block number without `#` is a timestamp in 1970.
+        let err = Until::parse("21000000").unwrap_err();
+        assert!(err.to_string().contains("#21000000"));
+        assert!(Until::parse("2020-01-01T00:00:00Z").is_err());
+        assert!(matches!(
+            Until::parse("4102444800"),
+            Ok(Until::Time(4102444800))
+        ));
+        assert!(matches!(
+            Until::parse("2100-01-01T00:00:00Z"),
+            Ok(Until::Time(4102444800))
+        ));
+        assert!(Until::parse("tomorrow").is_err());
+        assert!(Until::parse("").is_err());
+    }
+}
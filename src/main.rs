@@ -1,111 +1,133 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, bail, Result};
 use clap::Parser;
 use subxt::{
-    config::substrate::H256,
-    tx::{Signer, TxPayload},
+    backend::TransactionStatus, config::substrate::H256, tx::TxPayload, utils::AccountId32,
 };
-use subxt_signer::{
-    sr25519::{Keypair, Seed},
-    DeriveJunction,
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    task::JoinHandle,
 };
 
+use accounts::{Accounts, Derivation};
+use db::{Database, TxnKind, TxnStatus};
+use inscription::InscriptionProtocol;
+use key::Keypair;
+use metadata::Chain;
+
+mod accounts;
+mod db;
+mod indexer;
+mod inscription;
+mod key;
+mod keystore;
+mod limits;
 mod metadata;
+mod params;
+mod polkadot_js;
+mod report;
+mod status;
+mod supply;
+mod sweep;
+mod watcher;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[clap(long)]
     endpoint: Option<String>,
-    #[clap(long, required = true)]
-    private_key: String,
-    #[clap(long, required = true)]
-    remark: String,
-    #[clap(long, required = true)]
-    chain: String,
-    /// Specifies how many transactions to fill the mempool with.
+    #[command(flatten)]
+    root_key: key::RootKey,
+    /// The signature scheme of the root account and, hence, of the derived accounts.
+    #[clap(long, value_enum, default_value_t = key::Scheme::Sr25519)]
+    scheme: key::Scheme,
+    /// The derivation path of the accounts relative to the root account, with `{index}` standing
+    /// for the index of the account. Defaults to `//{index}`.
+    ///
+    /// The template is stored in the database on the first run and cannot be changed afterwards.
+    #[clap(long, value_name = "TEMPLATE")]
+    derivation: Option<String>,
+    #[command(flatten)]
+    inscription: inscription::InscriptionArgs,
+    #[clap(long, required = true, value_enum)]
+    chain: Option<Chain>,
+    /// Build the calls against the metadata fetched from the chain instead of the generated one.
+    ///
+    /// This happens automatically if the generated metadata doesn't match the chain anymore.
+    #[clap(long)]
+    dynamic_metadata: bool,
+    /// The number of transactions in flight. The hops are pre-signed in batches of half as many
+    /// and at most that many mints wait for inclusion at once. The transfers are still submitted
+    /// one at a time, since every one of them is funded by the previous.
     #[clap(long, short = 'n', default_value = "100")]
     inflight_num: usize,
+    /// The tip of every transaction: either an amount of the native token, e.g. `0.0001`, or a
+    /// percentile of the tips in the recent blocks, e.g. `p90`.
+    #[clap(long, value_name = "AMOUNT|pN")]
+    tip: Option<String>,
+    /// Make the transactions valid for the given number of blocks only, so that the stuck ones
+    /// expire instead of lingering in the pool forever.
+    #[clap(long, value_name = "BLOCKS")]
+    mortality: Option<u64>,
+    /// Keep every account alive instead of moving all of its funds to the next one.
+    #[clap(long)]
+    keep_alive: bool,
+    /// How much every account keeps when `--keep-alive` is given, in the native token, e.g.
+    /// `0.001`. Defaults to the existential deposit.
+    #[clap(long, value_name = "AMOUNT", requires = "keep_alive")]
+    reserve: Option<String>,
+    /// Stop once the max supply of the minted tick is reached and sweep the funds back to the
+    /// root account. The supply is looked up in the local index (`local`, see the `index`
+    /// command) or at the URL of an indexer returning `{"minted": ..., "max": ...}`, where
+    /// `{tick}` is replaced with the tick.
+    #[clap(long, value_name = "local|URL")]
+    supply: Option<String>,
+    /// Stop after submitting the given number of mints.
+    #[clap(long, value_name = "COUNT")]
+    max_mints: Option<u64>,
+    /// Stop once the fees paid by the run reach the amount of the native token, e.g. `0.5`. The
+    /// fees are known once the transactions are finalized, so the transactions in flight might
+    /// overshoot the limit.
+    #[clap(long, value_name = "AMOUNT")]
+    max_spend: Option<String>,
+    /// Stop at the given time, either a Unix timestamp or `YYYY-MM-DDTHH:MM:SSZ`, or once the
+    /// best block reaches `#BLOCK`.
+    #[clap(long, value_name = "TIME|#BLOCK")]
+    until: Option<String>,
+    /// How many of the blocks finalized since the last run are looked through for our
+    /// transactions at most. The nodes that prune the state only keep the recent blocks, 256 by
+    /// default.
+    #[clap(long, value_name = "BLOCKS", default_value = "256")]
+    max_catch_up: u32,
 }
 
 impl Cli {
-    fn ensure_kusama(&self) -> Result<()> {
-        if self.chain != "kusama" {
-            bail!("Only Kusama is supported");
-        }
-        Ok(())
-    }
-
-    fn private_key(&self) -> Result<Keypair> {
-        // strip 0x prefix
-        let private_key = if self.private_key.starts_with("0x") {
-            &self.private_key[2..]
-        } else {
-            &self.private_key
-        };
-        let raw = hex::decode(&private_key).map_err(|e| anyhow!(e))?;
-        let mut seed: Seed = Seed::default();
-        if raw.len() != seed.len() {
-            bail!(
-                "Keyfile length invalid, expected {} bytes, got {} bytes",
-                seed.len(),
-                raw.len()
-            );
-        }
-        seed.copy_from_slice(&raw[..]);
-        Ok(Keypair::from_seed(seed)?)
+    fn chain(&self) -> Result<Chain> {
+        self.chain.ok_or_else(|| anyhow!("--chain is required"))
     }
 }
 
-/// Takes a keypair and derives a new keypair from it.
-///
-/// Calling this function with the same parameters must return the same result.
-fn derive_account(keypair: &Keypair, index: u32) -> Keypair {
-    keypair.derive([DeriveJunction::hard(index)])
-}
-
-struct Database {
-    sqlite: sqlx::SqlitePool,
-}
-
-impl Database {
-    pub async fn new() -> Result<Self> {
-        let sqlite = sqlx::sqlite::SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect("sqlite://inscribed.sqlite")
-            .await?;
-        Ok(Self { sqlite })
-    }
-
-    /// Returns the next usable index for deriving an account.
-    pub async fn get_next_index(&self) -> Result<u32> {
-        let row = sqlx::query!("SELECT value FROM kv WHERE key = 'next_index'")
-            .fetch_one(&self.sqlite)
-            .await?;
-        let value = row
-            .value
-            .expect("next_index should be present")
-            .parse::<u32>()?;
-        Ok(value)
-    }
-
-    pub async fn update(&self, new_next_index: u32, txns: Vec<Txn>) -> Result<()> {
-        let mut tx = self.sqlite.begin().await?;
-        sqlx::query!(
-            "UPDATE kv SET value = ? WHERE key = 'next_index'",
-            new_next_index
-        )
-        .execute(&mut *tx)
-        .await?;
-        for txn in txns {
-            sqlx::query!("INSERT INTO txns (extrinsic_data) VALUES (?)", txn.0)
-                .execute(&mut *tx)
-                .await?;
-        }
-        tx.commit().await?;
-        Ok(())
-    }
+/// Without a subcommand, the inscribing is run.
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Manage the root keys in the local keystore.
+    #[command(subcommand)]
+    Key(keystore::KeyCommand),
+    /// Move the funds left in the derived accounts back to the root account.
+    Sweep(sweep::SweepArgs),
+    /// Summarize the progress recorded in the database along with the state of the chain.
+    Status(status::StatusArgs),
+    /// Report the fees paid so far, in total and per mint.
+    Report(report::ReportArgs),
+    /// Index the ksm-20 remarks on chain and report the balances of the derived accounts.
+    Index(indexer::IndexArgs),
 }
 
 struct AccountState {
@@ -114,142 +136,1248 @@ struct AccountState {
     nonce: u64,
 }
 
+type AccountInfo = metadata::kusama::runtime_types::frame_system::AccountInfo<
+    u32,
+    metadata::kusama::runtime_types::pallet_balances::types::AccountData<u128>,
+>;
+
 #[derive(Clone)]
-struct Txn(Vec<u8>);
+struct Txn {
+    kind: TxnKind,
+    /// The index of the derived account that signed this transaction. `None` for the root.
+    sender: Option<u32>,
+    nonce: u64,
+    /// The encoded signed extrinsic.
+    data: Vec<u8>,
+    /// The account the funds are transferred to, `None` for the mints.
+    dest: Option<AccountId32>,
+}
 
 impl Txn {
     pub fn hash(&self) -> H256 {
         use subxt::config::Hasher;
-        <metadata::kusama::Config as subxt::Config>::Hasher::hash_of(&self.0)
+        // `data` is already encoded, so it's hashed as is.
+        <metadata::Config as subxt::Config>::Hasher::hash(&self.data)
     }
 }
 
 #[derive(Clone)]
 struct Rpc {
-    client: metadata::kusama::Client,
+    client: metadata::Client,
+    legacy: metadata::LegacyRpc,
+    /// Whether the calls are built with the generated metadata or dynamically.
+    static_calls: bool,
+    params: params::Params,
+    /// The transactions whose inclusion the [`watcher`] looks out for.
+    inclusions: Arc<watcher::Inclusions>,
 }
 
 impl Rpc {
-    pub async fn new(endpoint: &str) -> Result<Self> {
-        let client = metadata::kusama::new_client(endpoint).await?;
-        Ok(Self { client })
+    pub async fn new(endpoint: &str, chain: Chain, dynamic_metadata: bool) -> Result<Self> {
+        let (client, legacy) = metadata::new_client(endpoint).await?;
+        let static_calls = chain.has_static_metadata() && !dynamic_metadata && {
+            let valid = metadata::validate_static(&client);
+            if !valid {
+                println!("generated metadata is outdated, falling back to dynamic metadata");
+            }
+            valid
+        };
+        Ok(Self {
+            client,
+            legacy,
+            static_calls,
+            params: Default::default(),
+            inclusions: Default::default(),
+        })
+    }
+
+    /// Returns the tip the transactions are signed with at the moment.
+    pub async fn current_tip(&self) -> Result<u128> {
+        let best = self.best_block_hash().await?;
+        self.params.tip(&self.client, best).await
+    }
+
+    /// Sets the tip and the mortality of the transactions signed from now on.
+    pub fn with_params(self, params: params::Params) -> Self {
+        Self { params, ..self }
     }
 
     pub async fn get_nonce(&self, keypair: &Keypair) -> Result<u64> {
-        let account_id =
-            <subxt_signer::sr25519::Keypair as Signer<metadata::kusama::Config>>::account_id(
-                keypair,
-            );
+        let account_id = keypair.account_id();
         let nonce = self.client.tx().account_nonce(&account_id).await?;
         Ok(nonce)
     }
 
-    pub fn sign_uxt(&self, keypair: &Keypair, nonce: u64, uxt: impl TxPayload) -> Result<Txn> {
-        let signed =
-            self.client
-                .tx()
-                .create_signed_with_nonce(&uxt, keypair, nonce, Default::default())?;
-        Ok(Txn(signed.into_encoded()))
+    pub async fn best_block_number(&self) -> Result<u32> {
+        let best = self.best_block_hash().await?;
+        let header = self
+            .client
+            .backend()
+            .block_header(best)
+            .await?
+            .ok_or_else(|| anyhow!("best block {:?} not found", best))?;
+        Ok(header.number)
+    }
+
+    pub async fn best_block_hash(&self) -> Result<H256> {
+        self.legacy
+            .chain_get_block_hash(None)
+            .await?
+            .ok_or_else(|| anyhow!("best block not found"))
+    }
+
+    /// Returns the nonce and the balances of the account as of the best block.
+    ///
+    /// The best block is used rather than the finalized one so that the transactions that were
+    /// just included are accounted for.
+    pub async fn get_account_info(&self, account_id: &AccountId32) -> Result<AccountInfo> {
+        let mut query = metadata::kusama::storage().system().account(account_id);
+        if !self.static_calls {
+            // The layout is the same on all the supported chains, but the type hashes may differ.
+            query = query.unvalidated();
+        }
+        let best = self.best_block_hash().await?;
+        let info = self
+            .client
+            .storage()
+            .at(best)
+            .fetch_or_default(&query)
+            .await?;
+        Ok(info)
+    }
+
+    /// Estimates the fee of the given transaction with `TransactionPaymentApi::query_info`. The tip
+    /// is not included.
+    pub async fn estimate_fee(&self, txn: &Txn) -> Result<u128> {
+        let uxt =
+            subxt::tx::SubmittableExtrinsic::from_bytes(self.client.clone(), txn.data.clone());
+        Ok(uxt.partial_fee_estimate().await?)
+    }
+
+    /// Returns the minimum balance an account must hold to exist.
+    pub fn existential_deposit(&self) -> Result<u128> {
+        if self.static_calls {
+            let address = metadata::kusama::constants()
+                .balances()
+                .existential_deposit();
+            return Ok(self.client.constants().at(&address)?);
+        }
+        let address = subxt::dynamic::constant("Balances", "ExistentialDeposit");
+        let value = self.client.constants().at(&address)?.to_value()?;
+        value
+            .as_u128()
+            .ok_or_else(|| anyhow!("ExistentialDeposit is not a number"))
+    }
+
+    pub async fn sign_uxt(
+        &self,
+        signer: &AccountState,
+        kind: TxnKind,
+        uxt: impl TxPayload,
+    ) -> Result<Txn> {
+        let best = self.best_block_hash().await?;
+        let params = self.params.build(&self.client, best).await?;
+        let signed = self.client.tx().create_signed_with_nonce(
+            &uxt,
+            &signer.keypair,
+            signer.nonce,
+            params,
+        )?;
+        Ok(Txn {
+            kind,
+            sender: signer.index,
+            nonce: signer.nonce,
+            data: signed.into_encoded(),
+            dest: None,
+        })
+    }
+
+    /// Submits the transaction and waits until it's included in the best block. Returns the hash
+    /// of that block.
+    ///
+    /// The status of the transaction is tracked in the database up to that point. The finality is
+    /// then picked up by the [`watcher`], which also follows the best blocks in case the status
+    /// stream of the transaction ends early.
+    pub async fn submit(&self, db: &Database, txn: Txn) -> Result<H256, SubmitError> {
+        let txn_hash = txn.hash();
+        let included = self.inclusions.wait(txn_hash)?;
+        let result = self.await_inclusion(db, &txn, included).await;
+        self.inclusions.forget(txn_hash);
+        let block_hash = result?;
+        self.verify(db, &txn, block_hash).await?;
+        Ok(block_hash)
     }
 
-    pub async fn submit(&self, txn: Txn) -> Result<H256> {
-        let mut progress = self.client.backend().submit_transaction(&txn.0).await?;
-        while let Some(status) = progress.next().await {
+    /// Waits for the transaction to be included in the best block, either through its status
+    /// stream or through the [`watcher`].
+    async fn await_inclusion(
+        &self,
+        db: &Database,
+        txn: &Txn,
+        mut included: oneshot::Receiver<H256>,
+    ) -> Result<H256, SubmitError> {
+        let txn_hash = txn.hash();
+        let mut progress = match self.client.backend().submit_transaction(&txn.data).await {
+            Ok(progress) => progress,
+            Err(err) => {
+                // The node may reject the transaction right away.
+                let err = SubmitError::from(err);
+                if let SubmitError::AlreadyImported = err {
+                    // An earlier submission is still in the pool, e.g. after the status stream
+                    // closed. Its inclusion is left to the watcher.
+                    return self.await_watcher(db, txn_hash, included).await;
+                }
+                if let Some(status) = err.status() {
+                    db.set_status_by_hash(txn_hash, status).await?;
+                }
+                return Err(err);
+            }
+        };
+        loop {
+            let status = tokio::select! {
+                status = progress.next() => status,
+                block_hash = &mut included => {
+                    return self.track_inclusion(db, txn_hash, block_hash).await;
+                }
+            };
+            // Once the stream ends, the inclusion is left to the watcher.
+            let Some(status) = status else { break };
             let status = status?;
+            self.track(db, txn_hash, &status).await?;
             match status {
-                subxt::backend::TransactionStatus::InBestBlock { hash }
-                | subxt::backend::TransactionStatus::InFinalizedBlock { hash } => {
-                    return Ok(hash.hash())
+                TransactionStatus::InBestBlock { hash }
+                | TransactionStatus::InFinalizedBlock { hash } => return Ok(hash.hash()),
+                TransactionStatus::Dropped { message } => {
+                    return Err(SubmitError::Dropped(message))
                 }
-                _ => (),
+                TransactionStatus::Invalid { message } => {
+                    return Err(SubmitError::Invalid(message))
+                }
+                TransactionStatus::Error { message } => return Err(SubmitError::Error(message)),
+                TransactionStatus::Validated
+                | TransactionStatus::Broadcasted { .. }
+                | TransactionStatus::NoLongerInBestBlock => (),
             }
         }
-        bail!("Transaction failed")
+        self.await_watcher(db, txn_hash, included).await
+    }
+
+    /// Waits for the [`watcher`] to report the inclusion of the transaction.
+    async fn await_watcher(
+        &self,
+        db: &Database,
+        txn_hash: H256,
+        included: oneshot::Receiver<H256>,
+    ) -> Result<H256, SubmitError> {
+        match tokio::time::timeout(INCLUSION_TIMEOUT, included).await {
+            Ok(block_hash) => self.track_inclusion(db, txn_hash, block_hash).await,
+            Err(_) => Err(SubmitError::StreamClosed),
+        }
+    }
+
+    /// Records the inclusion reported by the [`watcher`]. The error means the watcher stopped.
+    async fn track_inclusion(
+        &self,
+        db: &Database,
+        txn_hash: H256,
+        block_hash: Result<H256, oneshot::error::RecvError>,
+    ) -> Result<H256, SubmitError> {
+        let block_hash = block_hash.map_err(|_| anyhow!("The watcher stopped"))?;
+        let status = TransactionStatus::InBestBlock {
+            hash: block_hash.into(),
+        };
+        self.track(db, txn_hash, &status).await?;
+        Ok(block_hash)
+    }
+
+    /// Records the given status of the transaction in the database.
+    async fn track(
+        &self,
+        db: &Database,
+        txn_hash: H256,
+        status: &TransactionStatus<H256>,
+    ) -> Result<()> {
+        match status {
+            TransactionStatus::Validated
+            | TransactionStatus::Broadcasted { .. }
+            | TransactionStatus::NoLongerInBestBlock => {
+                db.set_status_by_hash(txn_hash, TxnStatus::Submitted)
+                    .await?
+            }
+            TransactionStatus::InBestBlock { hash } => {
+                let index = self.find_extrinsic(hash.hash(), txn_hash).await?;
+                db.set_included(txn_hash, TxnStatus::InBestBlock, hash.hash(), index)
+                    .await?
+            }
+            TransactionStatus::InFinalizedBlock { hash } => {
+                let index = self.find_extrinsic(hash.hash(), txn_hash).await?;
+                db.set_included(txn_hash, TxnStatus::Finalized, hash.hash(), index)
+                    .await?
+            }
+            // An error means the node gave up on watching the transaction. It's not in the pool
+            // as far as we are concerned.
+            TransactionStatus::Dropped { .. } | TransactionStatus::Error { .. } => {
+                db.set_status_by_hash(txn_hash, TxnStatus::Dropped).await?
+            }
+            TransactionStatus::Invalid { .. } => {
+                db.set_status_by_hash(txn_hash, TxnStatus::Invalid).await?
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the transaction included in the given block was dispatched successfully.
+    ///
+    /// Inclusion alone doesn't tell much: the extrinsic could have failed. Hence, we look for the
+    /// events that the transaction is expected to emit.
+    async fn verify(&self, db: &Database, txn: &Txn, block_hash: H256) -> Result<(), SubmitError> {
+        // The events are decoded against the live metadata by their names, so the generated types
+        // are fine for the other chains too.
+        use metadata::kusama::{balances, system};
+        use subxt::config::Hasher;
+
+        let txn_hash = txn.hash();
+        let extrinsics = self
+            .client
+            .blocks()
+            .at(block_hash)
+            .await?
+            .extrinsics()
+            .await?;
+        let mut found = None;
+        for ext in extrinsics.iter() {
+            let ext = ext?;
+            if <metadata::Config as subxt::Config>::Hasher::hash(ext.bytes()) == txn_hash {
+                found = Some(ext);
+                break;
+            }
+        }
+        let Some(ext) = found else {
+            return Err(anyhow!("txn {:?} not found in block {:?}", txn_hash, block_hash).into());
+        };
+        let events = ext.events().await?;
+
+        let reason = if let Some(failed) = events.find_first::<system::events::ExtrinsicFailed>()? {
+            format!("{:?}", failed.dispatch_error)
+        } else {
+            let succeeded = match txn.kind {
+                TxnKind::Mint => events.has::<system::events::Remarked>()?,
+                TxnKind::TransferAll | TxnKind::Transfer | TxnKind::Sweep => {
+                    events.has::<balances::events::Transfer>()?
+                }
+            };
+            if succeeded {
+                return Ok(());
+            }
+            format!("no expected event emitted by {}", txn.kind.as_str())
+        };
+        db.set_failed(txn_hash, &reason).await?;
+        Err(SubmitError::DispatchFailed(reason))
+    }
+
+    /// Returns the index of the extrinsic with the given hash within the given block.
+    async fn find_extrinsic(&self, block_hash: H256, txn_hash: H256) -> Result<Option<u32>> {
+        use subxt::config::Hasher;
+        let extrinsics = self
+            .client
+            .blocks()
+            .at(block_hash)
+            .await?
+            .extrinsics()
+            .await?;
+        for ext in extrinsics.iter() {
+            let ext = ext?;
+            let hash = <metadata::Config as subxt::Config>::Hasher::hash(ext.bytes());
+            if hash == txn_hash {
+                return Ok(Some(ext.index()));
+            }
+        }
+        Ok(None)
     }
 }
 
-fn sign_transfer_all(rpc: &Rpc, sender: &AccountState, receiver: &AccountState) -> Result<Txn> {
-    let xfer_uxt = metadata::kusama::tx().balances().transfer_all(
-        subxt::utils::MultiAddress::Id(<subxt_signer::sr25519::Keypair as Signer<
-            metadata::kusama::Config,
-        >>::account_id(&receiver.keypair)),
-        false,
-    );
-    let signed = rpc.sign_uxt(&sender.keypair, sender.nonce, xfer_uxt)?;
-    Ok(signed)
+/// Why a submitted transaction didn't make it into a block.
+#[derive(Debug)]
+enum SubmitError {
+    /// The transaction was dropped from the pool, e.g. because the pool is full. Submitting it
+    /// again may succeed.
+    Dropped(String),
+    /// The transaction is invalid, e.g. because its nonce is stale or the sender cannot pay the
+    /// fees. It has to be re-signed to succeed.
+    Invalid(String),
+    /// The node failed while processing the transaction.
+    Error(String),
+    /// The transaction was included, but failed to dispatch. Its nonce is used up.
+    DispatchFailed(String),
+    /// The status stream ended before the transaction was included.
+    StreamClosed,
+    /// The very same transaction is already in the pool.
+    AlreadyImported,
+    /// Failed to talk to the node or to the database.
+    Other(anyhow::Error),
+}
+
+impl SubmitError {
+    /// Returns true if submitting the very same transaction again may succeed.
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            SubmitError::Dropped(_) | SubmitError::StreamClosed | SubmitError::AlreadyImported
+        )
+    }
+
+    /// The status the failed transaction should be recorded with, if known.
+    fn status(&self) -> Option<TxnStatus> {
+        match self {
+            SubmitError::Dropped(_) => Some(TxnStatus::Dropped),
+            SubmitError::Invalid(_) => Some(TxnStatus::Invalid),
+            SubmitError::DispatchFailed(_) => Some(TxnStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl From<subxt::Error> for SubmitError {
+    fn from(err: subxt::Error) -> Self {
+        // The node reports the pool rejections as plain RPC errors, so that's the best we can do.
+        if let subxt::Error::Rpc(_) = err {
+            let message = err.to_string();
+            if message.contains("Invalid Transaction") {
+                return SubmitError::Invalid(message);
+            }
+            if message.contains("Immediately Dropped")
+                || message.contains("Priority is too low")
+                || message.contains("temporarily banned")
+            {
+                return SubmitError::Dropped(message);
+            }
+            if message.contains("Already Imported") {
+                return SubmitError::AlreadyImported;
+            }
+        }
+        SubmitError::Other(err.into())
+    }
+}
+
+impl From<anyhow::Error> for SubmitError {
+    fn from(err: anyhow::Error) -> Self {
+        SubmitError::Other(err)
+    }
+}
+
+impl std::fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubmitError::Dropped(message) => write!(f, "Transaction dropped: {}", message),
+            SubmitError::Invalid(message) => write!(f, "Transaction invalid: {}", message),
+            SubmitError::Error(message) => write!(f, "Transaction error: {}", message),
+            SubmitError::DispatchFailed(reason) => write!(f, "Transaction failed: {}", reason),
+            SubmitError::StreamClosed => write!(f, "Transaction status stream closed"),
+            SubmitError::AlreadyImported => write!(f, "Transaction already imported"),
+            SubmitError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// How many times a transaction is submitted before giving up on it.
+const MAX_ATTEMPTS: usize = 3;
+
+/// How long the [`watcher`] is waited on to see a transaction in a best block once its status
+/// stream ended.
+const INCLUSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Signs the transfer of everything the sender has to `dest`. The kind tells a hop of the chain
+/// from a sweep.
+async fn sign_transfer_all(
+    rpc: &Rpc,
+    sender: &AccountState,
+    dest: AccountId32,
+    kind: TxnKind,
+) -> Result<Txn> {
+    let signed = if rpc.static_calls {
+        let xfer_uxt = metadata::kusama::tx()
+            .balances()
+            .transfer_all(subxt::utils::MultiAddress::Id(dest.clone()), false);
+        rpc.sign_uxt(sender, kind, xfer_uxt).await?
+    } else {
+        let xfer_uxt = metadata::dynamic::transfer_all(dest.clone(), false);
+        rpc.sign_uxt(sender, kind, xfer_uxt).await?
+    };
+    Ok(Txn {
+        dest: Some(dest),
+        ..signed
+    })
+}
+
+/// Signs the transfer of `amount` to `dest` that fails rather than reaping the sender.
+async fn sign_transfer_keep_alive(
+    rpc: &Rpc,
+    sender: &AccountState,
+    dest: AccountId32,
+    amount: u128,
+) -> Result<Txn> {
+    let signed = if rpc.static_calls {
+        let xfer_uxt = metadata::kusama::tx()
+            .balances()
+            .transfer_keep_alive(subxt::utils::MultiAddress::Id(dest.clone()), amount);
+        rpc.sign_uxt(sender, TxnKind::Transfer, xfer_uxt).await?
+    } else {
+        let xfer_uxt = metadata::dynamic::transfer_keep_alive(dest.clone(), amount);
+        rpc.sign_uxt(sender, TxnKind::Transfer, xfer_uxt).await?
+    };
+    Ok(Txn {
+        dest: Some(dest),
+        ..signed
+    })
 }
 
-fn sign_mint(rpc: &Rpc, minter: &AccountState, remark: Vec<u8>) -> Result<Txn> {
-    let uxt = metadata::kusama::tx().system().remark_with_event(remark);
-    let signed = rpc.sign_uxt(&minter.keypair, minter.nonce, uxt)?;
+async fn sign_mint(
+    rpc: &Rpc,
+    minter: &AccountState,
+    inscription: &dyn InscriptionProtocol,
+) -> Result<Txn> {
+    let remark = inscription.remark();
+    inscription.validate(&remark)?;
+    let signed = if rpc.static_calls {
+        let uxt = metadata::kusama::tx().system().remark_with_event(remark);
+        rpc.sign_uxt(minter, TxnKind::Mint, uxt).await?
+    } else {
+        let uxt = metadata::dynamic::remark_with_event(remark);
+        rpc.sign_uxt(minter, TxnKind::Mint, uxt).await?
+    };
     Ok(signed)
 }
 
+/// Reconciles the transactions that were persisted but not known to be included on chain, e.g.
+/// because the previous run was interrupted before or during the submission.
+///
+/// The transactions are checked in the order they were persisted. If the nonce of a transaction
+/// was already used on chain, it is considered confirmed. See [`reconcile`] for the senders reaped
+/// by a transfer. If the sender has no funds or the nonce is unreachable, it is obsolete.
+/// Otherwise, the transaction is rebroadcasted.
+///
+/// If a transfer of the chain didn't make it, the funds are still with the destination of the last
+/// one that did, so the accounts after it are handed back and the next run continues from there.
+async fn resume(rpc: &Rpc, db: &Database, accounts: &Accounts) -> Result<()> {
+    let pending = db.pending_txns().await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    println!("reconciling {} pending transactions", pending.len());
+    // The destination of the last forward that landed and the forwards that didn't seem to.
+    let mut landed: Option<u32> = None;
+    let mut missed = Vec::new();
+    for (id, txn) in pending {
+        let forwards = matches!(txn.kind, TxnKind::TransferAll | TxnKind::Transfer);
+        let dest = txn.sender.map_or(0, |sender| sender + 1);
+        let keypair = accounts.get(txn.sender);
+        let info = rpc.get_account_info(&keypair.account_id()).await?;
+        let free = info.data.free;
+        // Where a reaped sender moved its funds to. A mint is followed by the transfer of its hop,
+        // while the sweeps go anywhere and the old ones didn't record where.
+        let reaper = match (&txn.dest, txn.kind) {
+            (Some(dest), _) => Some(dest.clone()),
+            (None, TxnKind::Sweep) => None,
+            (None, _) => Some(accounts.derive(dest).account_id()),
+        };
+        let reaper_funded = match reaper {
+            Some(reaper) if info.nonce == 0 && free == 0 => is_funded(rpc, &reaper).await?,
+            _ => false,
+        };
+        let status = match reconcile(&txn, info.nonce as u64, free, reaper_funded) {
+            Reconciled::Landed => {
+                println!("txn {:?} is already included", txn.hash());
+                TxnStatus::Confirmed
+            }
+            Reconciled::Obsolete => {
+                println!("txn {:?} is obsolete", txn.hash());
+                TxnStatus::Obsolete
+            }
+            Reconciled::Pending => {
+                println!("rebroadcasting txn {:?}", txn.hash());
+                match rpc.submit(db, txn).await {
+                    // The status is already tracked by the submission.
+                    Ok(_) => {
+                        if forwards {
+                            landed = landed.max(Some(dest));
+                        }
+                        continue;
+                    }
+                    Err(err) => {
+                        println!("rebroadcast failed: {}", err);
+                        // The submission records the status it knows of, the rest are given up on.
+                        err.status().unwrap_or(TxnStatus::Obsolete)
+                    }
+                }
+            }
+        };
+        if forwards && status == TxnStatus::Confirmed {
+            landed = landed.max(Some(dest));
+        } else if forwards {
+            missed.push((id, dest, status));
+            continue;
+        }
+        db.set_status(id, status).await?;
+    }
+
+    // The destination of a forward could have been reaped by forwarding the funds in turn, so
+    // anything before the last forward that landed has landed as well.
+    let mut first_missed = None;
+    for (id, dest, status) in missed {
+        if Some(dest) <= landed {
+            db.set_status(id, TxnStatus::Confirmed).await?;
+            continue;
+        }
+        db.set_status(id, status).await?;
+        first_missed = Some(first_missed.map_or(dest, |first: u32| first.min(dest)));
+    }
+    let Some(first_missed) = first_missed else {
+        return Ok(());
+    };
+    // The funds are with the destination of the last forward that landed, the accounts after it
+    // were never funded.
+    let holder_next = landed.map_or(first_missed, |landed| landed + 1);
+    let next_index = db.get_next_index().await?;
+    if holder_next < next_index {
+        println!("handing back the accounts from {}", holder_next);
+        db.update(holder_next, Vec::new(), Vec::new()).await?;
+    }
+    Ok(())
+}
+
+/// What became of a pending transaction, as far as the account of its sender tells.
+#[derive(Debug, PartialEq, Eq)]
+enum Reconciled {
+    /// The nonce of the transaction is used.
+    Landed,
+    /// Either a preceding transaction of the same sender never made it or there is nothing to pay
+    /// the fees with.
+    Obsolete,
+    /// The transaction could still make it.
+    Pending,
+}
+
+/// Judges a pending transaction by the nonce and the free balance of its sender on chain.
+///
+/// A `transfer_all` reaps the sender, which resets its nonce. So a sender without the nonce and
+/// the funds used its nonces if the account the funds were moved to, `reaper_funded`, got them.
+fn reconcile(txn: &Txn, nonce: u64, free: u128, reaper_funded: bool) -> Reconciled {
+    if nonce > txn.nonce || (nonce == 0 && free == 0 && reaper_funded) {
+        Reconciled::Landed
+    } else if nonce < txn.nonce || free == 0 {
+        Reconciled::Obsolete
+    } else {
+        Reconciled::Pending
+    }
+}
+
+/// Whether the account has ever received the funds.
+async fn is_funded(rpc: &Rpc, account_id: &AccountId32) -> Result<bool> {
+    let info = rpc.get_account_info(account_id).await?;
+    Ok(info.nonce > 0 || info.data.free > 0)
+}
+
+/// Submits the transfer of a hop, recovering from the failures that could be recovered from.
+///
+/// `failed_mints` receives the senders of the mints that were given up on. If that's the sender of
+/// the transfer, then the transfer was signed with a nonce that will never be reached.
+///
+/// Returns `false` if the sender ran out of funds and the run cannot continue.
+async fn submit_xfer(
+    rpc: &Rpc,
+    db: &Database,
+    accounts: &Accounts,
+    forward: Forward,
+    mut xfer: Txn,
+    failed_mints: &mut mpsc::UnboundedReceiver<Option<u32>>,
+) -> Result<bool> {
+    let mut attempt = 1;
+    loop {
+        let submission = rpc.submit(db, xfer.clone());
+        tokio::pin!(submission);
+        let result = loop {
+            tokio::select! {
+                result = &mut submission => break result,
+                Some(sender) = failed_mints.recv() => {
+                    if sender == xfer.sender {
+                        break Err(SubmitError::Invalid("the preceding mint failed".to_string()));
+                    }
+                }
+            }
+        };
+        let err = match result {
+            Ok(_) => return Ok(true),
+            Err(SubmitError::Other(err)) => return Err(err),
+            Err(err) if attempt >= MAX_ATTEMPTS => bail!(err),
+            Err(err) => err,
+        };
+        attempt += 1;
+        println!("transfer {:?} failed: {}", xfer.hash(), err);
+        if err.is_retryable() {
+            continue;
+        }
+        let SubmitError::Invalid(_) = err else {
+            bail!(err);
+        };
+
+        // Most likely the nonce is off. Re-sign with the actual one unless there is nothing to
+        // transfer anymore.
+        let keypair = accounts.get(xfer.sender);
+        let info = rpc.get_account_info(&keypair.account_id()).await?;
+        let nonce = info.nonce as u64;
+        let free = info.data.free;
+        let dest_index = xfer.sender.map_or(0, |index| index + 1);
+        if nonce > xfer.nonce {
+            // The transfer has already made it.
+            return Ok(true);
+        }
+        if nonce == 0
+            && free == 0
+            && is_funded(rpc, &accounts.derive(dest_index).account_id()).await?
+        {
+            // The transfer of the whole balance has made it and reaped the sender.
+            return Ok(true);
+        }
+        if free <= forward.reserve() {
+            println!("account {:?} has no funds left", xfer.sender);
+            return Ok(false);
+        }
+        // The nonce is fine, but a mortal transaction could have expired. Re-signing renews it.
+        if nonce == xfer.nonce && !rpc.params.is_mortal() {
+            bail!(err);
+        }
+        let abandoned = xfer.hash();
+        let prev = AccountState {
+            keypair,
+            nonce,
+            index: xfer.sender,
+        };
+        let dest = accounts.derive(dest_index).account_id();
+        println!(
+            "re-signing transfer from {:?} with nonce {}",
+            prev.index, nonce
+        );
+        xfer = match forward {
+            Forward::All => sign_transfer_all(rpc, &prev, dest, TxnKind::TransferAll).await?,
+            Forward::KeepAlive { reserve } => {
+                // The transfer of the whole balance is at least as long, and so is its fee.
+                let sample = sign_transfer_keep_alive(rpc, &prev, dest.clone(), free).await?;
+                let fee = with_margin(rpc.estimate_fee(&sample).await?) + rpc.current_tip().await?;
+                let Some(amount) = free.checked_sub(fee + reserve) else {
+                    println!("account {:?} has no funds left", xfer.sender);
+                    return Ok(false);
+                };
+                sign_transfer_keep_alive(rpc, &prev, dest, amount).await?
+            }
+        };
+        // The abandoned submission could have been cut short and left the transfer as submitted.
+        db.set_status_by_hash(abandoned, TxnStatus::Obsolete)
+            .await?;
+        db.insert(xfer.clone()).await?;
+    }
+}
+
+/// A single step of the chain: moves the funds from the previous account to the next one and then
+/// mints from the latter.
+struct Hop {
+    xfer: Txn,
+    mint: Txn,
+}
+
+/// How a hop moves the funds to the next account.
+#[derive(Clone, Copy, Debug)]
+enum Forward {
+    /// Everything is transferred and the previous account gets reaped.
+    All,
+    /// The previous account keeps `reserve` and stays alive.
+    KeepAlive { reserve: u128 },
+}
+
+impl Forward {
+    fn reserve(&self) -> u128 {
+        match self {
+            Forward::All => 0,
+            Forward::KeepAlive { reserve } => *reserve,
+        }
+    }
+}
+
+/// How much the estimated fees are raised by, in percent. The fee multiplier may go up between the
+/// signing and the inclusion, and a kept alive account must not end up below the reserve.
+const FEE_MARGIN_PERCENT: u128 = 10;
+
+fn with_margin(fee: u128) -> u128 {
+    fee + fee * FEE_MARGIN_PERCENT / 100
+}
+
+/// Signs up to `count` hops, the first of which funds the account derived at `index`. Only as many
+/// hops are signed as the funds are enough for. Also returns whether the funds ran out.
+///
+/// Every hop pays for the transfer, leaves the reserve behind and funds the next account, which
+/// then pays for the mint. What's left must pay for the transfer of the following hop and still
+/// endow the account it funds with the existential deposit.
+async fn sign_batch(
+    rpc: &Rpc,
+    chain: Chain,
+    accounts: &Accounts,
+    forward: Forward,
+    index: u32,
+    count: usize,
+    inscription: &dyn InscriptionProtocol,
+) -> Result<(Vec<Hop>, bool)> {
+    let existential_deposit = rpc.existential_deposit()?;
+    let reserve = forward.reserve();
+    // The hops are alike, and so are their fees. The amount of a keep-alive transfer is encoded
+    // compactly, so the largest one makes for the longest transfer and the highest length fee.
+    let sample = sign_hop(rpc, accounts, index, inscription, forward, u128::MAX).await?;
+    let tip = rpc.current_tip().await?;
+    let xfer_fee = with_margin(rpc.estimate_fee(&sample.xfer).await?) + tip;
+    let mint_fee = with_margin(rpc.estimate_fee(&sample.mint).await?) + tip;
+
+    let holder = accounts.get(index.checked_sub(1));
+    let info = rpc.get_account_info(&holder.account_id()).await?;
+    let mut funds = info.data.free;
+    if index > 0 && info.nonce == 0 {
+        // The mint of the holder is not included yet, but it's going to be paid for.
+        funds = funds.saturating_sub(mint_fee);
+    }
+    let required = 2 * xfer_fee + mint_fee + 2 * reserve + existential_deposit;
+    let mut hops = Vec::with_capacity(count);
+    while hops.len() < count && funds >= required {
+        let amount = funds - xfer_fee - reserve;
+        let hop_index = index + hops.len() as u32;
+        hops.push(sign_hop(rpc, accounts, hop_index, inscription, forward, amount).await?);
+        funds = amount - mint_fee;
+    }
+    let exhausted = hops.len() < count;
+    if exhausted {
+        println!(
+            "funds run out: {} left, {} required per hop, stopping after {} more",
+            chain.format_balance(funds),
+            chain.format_balance(required),
+            hops.len()
+        );
+    }
+    Ok((hops, exhausted))
+}
+
+/// Signs the hop that funds the account derived at `index`. `amount` is only transferred when
+/// the previous account is kept alive, otherwise everything is.
+async fn sign_hop(
+    rpc: &Rpc,
+    accounts: &Accounts,
+    index: u32,
+    inscription: &dyn InscriptionProtocol,
+    forward: Forward,
+    amount: u128,
+) -> Result<Hop> {
+    let prev = if index == 0 {
+        // first time, use the root keypair. Request the nonce.
+        let keypair = Keypair::clone(accounts.root());
+        let nonce = rpc.get_nonce(&keypair).await?;
+        AccountState {
+            keypair,
+            nonce,
+            index: None,
+        }
+    } else {
+        // otherwise, derive the keypair from the previous one. The nonce must be 1 because the
+        // previous account should've submitted the mint transaction.
+        let keypair = accounts.derive(index - 1);
+        AccountState {
+            keypair,
+            nonce: 1,
+            index: Some(index - 1),
+        }
+    };
+    let next = {
+        // Next always has nonce 0.
+        let keypair = accounts.derive(index);
+        AccountState {
+            keypair,
+            nonce: 0,
+            index: Some(index),
+        }
+    };
+
+    println!("signing transfer from {:?} to {:?}", prev.index, next.index);
+    println!("nonce: {}", prev.nonce);
+    let dest = next.keypair.account_id();
+    let xfer = match forward {
+        Forward::All => sign_transfer_all(rpc, &prev, dest, TxnKind::TransferAll).await?,
+        Forward::KeepAlive { .. } => sign_transfer_keep_alive(rpc, &prev, dest, amount).await?,
+    };
+    println!("hash: {:?}", xfer.hash());
+
+    println!("signing mint from {:?}", next.index);
+    let mint = sign_mint(rpc, &next, inscription).await?;
+    println!("hash: {:?}", mint.hash());
+
+    Ok(Hop { xfer, mint })
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Key(command)) => command.clone().run(cli.chain, cli.scheme),
+        Some(Command::Sweep(args)) => sweep::run(&connect(&cli).await?, args).await,
+        Some(Command::Status(args)) => status::run(&cli, args).await,
+        Some(Command::Report(args)) => report::run(&cli, args).await,
+        Some(Command::Index(args)) => indexer::run(&cli, args).await,
+        None => run(cli).await,
+    }
+}
+
+/// Everything needed to talk to the chain on behalf of the accounts.
+struct Session {
+    chain: Chain,
+    rpc: Rpc,
+    db: Database,
+    accounts: Accounts,
+}
 
+/// Connects to the chain and the database and brings the latter up to date: the finalized blocks
+/// are watched from now on and the pending transactions are reconciled.
+async fn connect(cli: &Cli) -> Result<Session> {
     // first CLI stuff. Ensure it's all correct.
-    cli.ensure_kusama()?;
-    let root_keypair = Arc::new(cli.private_key()?);
+    let chain = cli.chain()?;
+    let root_keypair = cli.root_key.keypair(cli.scheme)?;
 
-    println!("remark: {}", &cli.remark);
+    println!("chain: {:?}", chain);
+    println!(
+        "root account: {}",
+        chain.to_ss58(&root_keypair.account_id())
+    );
 
-    let endpoint = metadata::kusama::pick_endpoint(cli.endpoint.as_deref());
+    let endpoint = chain.pick_endpoint(cli.endpoint.as_deref());
     println!("connecting to {}", &endpoint);
-    let rpc = Rpc::new(&endpoint).await?;
-    let remark = Arc::new(cli.remark.as_bytes().to_vec());
+    let tip = match &cli.tip {
+        Some(tip) => params::Tip::parse(chain, tip)?,
+        None => params::Tip::Fixed(0),
+    };
+    let rpc = Rpc::new(endpoint, chain, cli.dynamic_metadata)
+        .await?
+        .with_params(params::Params::new(tip, cli.mortality));
 
     let db = Database::new().await?;
+    db.check_genesis_hash(rpc.client.genesis_hash()).await?;
+    {
+        let (rpc, db) = (rpc.clone(), db.clone());
+        let max_catch_up = cli.max_catch_up;
+        tokio::spawn(async move {
+            // The submissions fail from now on, which stops the run.
+            if let Err(err) = watcher::run(rpc, db, max_catch_up).await {
+                println!("watcher failed: {:#}", err);
+            }
+        });
+    }
+    let stored_derivation = db.get_derivation().await?;
+    let derivation = match (&stored_derivation, &cli.derivation) {
+        (Some(stored), Some(derivation)) if stored != derivation => {
+            bail!("The accounts in the database are derived with {}", stored);
+        }
+        (Some(template), _) | (None, Some(template)) => Derivation::parse(template)?,
+        (None, None) => Derivation::parse(Derivation::DEFAULT)?,
+    };
+    println!("derivation: {}", derivation.as_str());
+    let accounts = Accounts::new(root_keypair, derivation)?;
+    if stored_derivation.is_none() {
+        db.set_derivation(accounts.derivation().as_str()).await?;
+    }
+    resume(&rpc, &db, &accounts).await?;
+
+    // The accounts derived before they were recorded.
+    let next_index = db.get_next_index().await?;
+    let recorded = db.get_account_count().await?;
+    if recorded < next_index {
+        let records = (recorded..next_index)
+            .map(|index| accounts.record(chain, index))
+            .collect();
+        db.record_accounts(records).await?;
+    }
+
+    Ok(Session {
+        chain,
+        rpc,
+        db,
+        accounts,
+    })
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let inscription = cli.inscription.inscription()?;
+    if !inscription.is_repeatable() {
+        bail!(
+            "The {} inscription could not be repeated, only the mints could",
+            inscription.name()
+        );
+    }
+
+    // Every hop consists of two transactions. A mint can only enter the pool once the transfer
+    // funding its account is in the best block, but the transfer to the following account is
+    // signed by the same account and thus can be submitted right along with the mint. That gives
+    // one hop per block at most, the rest of the inflight budget is spent on the mints that are
+    // still waiting for inclusion.
+    let inflight = Arc::new(Semaphore::new(cli.inflight_num.max(2)));
+    let hops_per_batch = (cli.inflight_num / 2).max(1);
+    // The hops are pre-signed, so the last one of a batch waits for a block per every hop before.
+    if let Some(mortality) = cli.mortality {
+        let min_mortality = 4 * hops_per_batch as u64;
+        if mortality < min_mortality {
+            bail!(
+                "--mortality must be at least {} blocks to cover a batch, or lower --inflight-num",
+                min_mortality
+            );
+        }
+    }
+
+    let mut limits = limits::Limits::new(
+        cli.chain()?,
+        cli.max_mints,
+        cli.max_spend.as_deref(),
+        cli.until.as_deref(),
+    )?;
+
+    let Session {
+        chain,
+        rpc,
+        db,
+        accounts,
+    } = connect(&cli).await?;
+    limits.begin(&db).await?;
+    let started = Instant::now();
+    println!(
+        "{} remark: {}",
+        inscription.name(),
+        String::from_utf8_lossy(&inscription.remark())
+    );
+    let forward = if cli.keep_alive {
+        let existential_deposit = rpc.existential_deposit()?;
+        let reserve = match &cli.reserve {
+            Some(reserve) => chain.parse_balance(reserve)?,
+            None => existential_deposit,
+        };
+        // Whatever is left below the existential deposit would be burned along with the account.
+        if reserve < existential_deposit {
+            bail!(
+                "The reserve must be at least the existential deposit of {}",
+                chain.format_balance(existential_deposit)
+            );
+        }
+        println!("keeping {} in every account", chain.format_balance(reserve));
+        Forward::KeepAlive { reserve }
+    } else {
+        Forward::All
+    };
+    let mut supply = match &cli.supply {
+        Some(source) => {
+            let endpoint = chain.pick_endpoint(cli.endpoint.as_deref());
+            let check =
+                supply::SupplyCheck::new(source, inscription.as_ref(), chain, endpoint, &db)
+                    .await?;
+            Some(check)
+        }
+        None => None,
+    };
+    let mut supply_checked = false;
+    let mut minted_out = false;
     let mut index = db.get_next_index().await?;
-    loop {
-        let prev = if index == 0 {
-            // first time, use the root keypair. Request the nonce.
-            let keypair = Keypair::clone(&root_keypair);
-            let nonce = rpc.get_nonce(&keypair).await?;
-            AccountState {
-                keypair,
-                nonce,
-                index: None,
+    let first_index = index;
+    let mut submitted = 0;
+    let stop_reason;
+
+    let mut mints: Vec<JoinHandle<()>> = Vec::new();
+    let (failed_mints_tx, mut failed_mints) = mpsc::unbounded_channel();
+    'run: loop {
+        // The mints in flight are not accounted for, so the supply might be overshot by a batch.
+        if let Some(supply) = &mut supply {
+            match supply.is_exhausted(&db).await {
+                Ok(true) => {
+                    stop_reason = "the supply is minted out".to_string();
+                    minted_out = true;
+                    break 'run;
+                }
+                Ok(false) => supply_checked = true,
+                // A flaky indexer shouldn't stop the run, unless it never worked in the first place.
+                Err(err) if supply_checked => println!("supply check failed: {:#}", err),
+                Err(err) => return Err(err),
             }
-        } else {
-            // otherwise, derive the keypair from the previous one. The nonce must be 1 because the
-            // previous account should've submitted the mint transaction.
-            let keypair = derive_account(&root_keypair, index - 1);
-            AccountState {
-                keypair,
-                nonce: 1,
-                index: Some(index - 1),
+        }
+
+        if let Some(reason) = limits.reached(chain, &rpc, &db, submitted).await? {
+            stop_reason = reason;
+            break 'run;
+        }
+        let count = limits
+            .remaining_mints(submitted)
+            .map_or(hops_per_batch, |remaining| {
+                hops_per_batch.min(remaining as usize)
+            });
+
+        // Pre-sign the next batch of hops and persist them before submitting anything. We also
+        // bump the index of the used accounts! Should we crash, the batch is picked up by `resume`.
+        let (hops, exhausted) = sign_batch(
+            &rpc,
+            chain,
+            &accounts,
+            forward,
+            index,
+            count,
+            inscription.as_ref(),
+        )
+        .await?;
+        let records = (index..index + hops.len() as u32)
+            .map(|index| accounts.record(chain, index))
+            .collect();
+        index += hops.len() as u32;
+        let txns = hops
+            .iter()
+            .flat_map(|hop| [hop.xfer.clone(), hop.mint.clone()])
+            .collect();
+        db.update(index, txns, records).await?;
+
+        let mut hops = hops.into_iter();
+        while let Some(hop) = hops.next() {
+            if let Some(reason) = limits.reached(chain, &rpc, &db, submitted).await? {
+                // The rest of the batch was never submitted. It's given up on and the accounts are
+                // handed back, so that the next run continues from the account holding the funds.
+                let unsent = std::iter::once(hop).chain(hops).collect::<Vec<_>>();
+                for hop in &unsent {
+                    db.set_status_by_hash(hop.xfer.hash(), TxnStatus::Obsolete)
+                        .await?;
+                    db.set_status_by_hash(hop.mint.hash(), TxnStatus::Obsolete)
+                        .await?;
+                }
+                index -= unsent.len() as u32;
+                db.update(index, Vec::new(), Vec::new()).await?;
+                stop_reason = reason;
+                break 'run;
             }
-        };
-        let next = {
-            // Next always has nonce 0.
-            let keypair = derive_account(&root_keypair, index);
-            AccountState {
-                keypair,
-                nonce: 0,
-                index: Some(index),
+
+            // The transfer has to land before the next account is able to pay for anything.
+            let permit = inflight.clone().acquire_owned().await?;
+            let landed =
+                submit_xfer(&rpc, &db, &accounts, forward, hop.xfer, &mut failed_mints).await?;
+            drop(permit);
+            if !landed {
+                stop_reason = "no funds left to transfer".to_string();
+                break 'run;
+            }
+
+            let permit = inflight.clone().acquire_owned().await?;
+            let (rpc, db, failed_mints_tx) = (rpc.clone(), db.clone(), failed_mints_tx.clone());
+            mints.push(tokio::spawn(async move {
+                let _permit = permit;
+                let sender = hop.mint.sender;
+                for _ in 0..MAX_ATTEMPTS {
+                    match rpc.submit(&db, hop.mint.clone()).await {
+                        Ok(block_hash) => {
+                            println!("mint included in {:?}", block_hash);
+                            return;
+                        }
+                        Err(err) if err.is_retryable() => {
+                            println!("mint dropped, retrying: {}", err)
+                        }
+                        Err(SubmitError::DispatchFailed(reason)) => {
+                            // The nonce is used up, so the transfer is not affected.
+                            println!("mint failed on chain: {}", reason);
+                            return;
+                        }
+                        Err(err) => {
+                            println!("mint failed: {}", err);
+                            break;
+                        }
+                    }
+                }
+                // Let the transfer from the same account know that it has to be re-signed.
+                // The transfer checks the nonce on chain, so that's fine for the mints whose
+                // fate is unknown, too.
+                let _ = failed_mints_tx.send(sender);
+            }));
+            submitted += 1;
+
+            // Reap the finished mints. A failed mint doesn't stop the run.
+            let (finished, pending): (Vec<_>, Vec<_>) =
+                mints.drain(..).partition(|mint| mint.is_finished());
+            mints = pending;
+            for mint in finished {
+                mint.await?;
             }
+        }
+        if exhausted {
+            stop_reason = "the funds ran out".to_string();
+            break 'run;
+        }
+    }
+
+    println!("waiting for {} mints in flight", mints.len());
+    for mint in mints {
+        mint.await?;
+    }
+    println!("stopped: {}", stop_reason);
+    println!(
+        "submitted {} mints from the accounts {}..{} in {}s",
+        submitted,
+        first_index,
+        index,
+        started.elapsed().as_secs()
+    );
+    // The fees of the transactions that are yet to be finalized are not known.
+    println!(
+        "spent {} in the fees recorded so far",
+        chain.format_balance(limits.spent(&db).await?)
+    );
+
+    if minted_out {
+        let root = accounts.root().account_id();
+        let session = Session {
+            chain,
+            rpc,
+            db,
+            accounts,
         };
+        sweep::sweep(&session, root, sweep::DEFAULT_CONCURRENCY).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txn(kind: TxnKind, nonce: u64) -> Txn {
+        Txn {
+            kind,
+            sender: Some(1),
+            nonce,
+            data: Vec::new(),
+            dest: None,
+        }
+    }
+
+    #[test]
+    fn reconcile_used_nonce() {
+        let mint = txn(TxnKind::Mint, 0);
+        assert_eq!(reconcile(&mint, 2, 100, false), Reconciled::Landed);
+        assert_eq!(reconcile(&mint, 0, 100, false), Reconciled::Pending);
+        assert_eq!(
+            reconcile(&txn(TxnKind::Mint, 3), 2, 100, false),
+            Reconciled::Obsolete
+        );
+    }
 
-        // Sign the transfer_all from the previous account to the next account.
-        // Saves the transaction to the database. We also bump the index of the used account!
-        println!("signing transfer_all from {:?} to {:?}", prev.index, next.index);
-        println!("nonce: {}", prev.nonce);
-        let xfer = sign_transfer_all(&rpc, &prev, &next)?;
-        println!("hash: {:?}", xfer.hash());
-        index += 1;
-        db.update(index, vec![xfer.clone()]).await?;
-        rpc.submit(xfer).await?;
-
-        // Sign the mint transaction from the next account. Saves the it into the database without
-        // changing the index.
-        println!("signing mint from {:?}", next.index);
-        let mint = sign_mint(&rpc, &next, Vec::clone(&remark))?;
-        println!("hash: {:?}", mint.hash());
-        db.update(index, vec![mint.clone()]).await?;
-        let tx_hash = rpc.submit(mint).await?;
-
-        println!("submitted tx: {:?}", tx_hash);
+    #[test]
+    fn reconcile_reaped_sender() {
+        // The mint with nonce 0 is followed by the transfer of everything with nonce 1, after
+        // which the sender is gone.
+        for kind in [TxnKind::Mint, TxnKind::TransferAll, TxnKind::Sweep] {
+            assert_eq!(reconcile(&txn(kind, 0), 0, 0, true), Reconciled::Landed);
+            assert_eq!(reconcile(&txn(kind, 1), 0, 0, true), Reconciled::Landed);
+            // The funds went nowhere, so the sender never had them.
+            assert_eq!(reconcile(&txn(kind, 0), 0, 0, false), Reconciled::Obsolete);
+        }
     }
 }
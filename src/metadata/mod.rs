@@ -4,34 +4,220 @@
 //     --edition=2021 --emit=stdout > src/metadata/kusama.rs
 mod kusama_gen;
 
+use subxt::utils::AccountId32;
+
+/// All the supported chains are close enough to the Substrate defaults.
+pub type Config = subxt::SubstrateConfig;
+pub type Client = subxt::OnlineClient<Config>;
+/// The RPC methods the backend doesn't expose, e.g. the hash of the best block.
+pub type LegacyRpc = subxt::backend::legacy::LegacyRpcMethods<Config>;
+/// The signing parameters of the transactions, e.g. the tip and the mortality.
+pub type OtherParams =
+    <<Config as subxt::Config>::ExtrinsicParams as subxt::config::ExtrinsicParams<Config>>::OtherParams;
+
 pub mod kusama {
     pub use super::kusama_gen::api::*;
+}
+
+/// The chains the inscriber is able to work with.
+///
+/// Only Kusama has the metadata generated at build time. The calls for the rest of the chains are
+/// built dynamically against the metadata fetched at runtime, see [`dynamic`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Chain {
+    Kusama,
+    Polkadot,
+    Westend,
+    KusamaAssetHub,
+    PolkadotAssetHub,
+}
 
-    pub type Config = subxt::SubstrateConfig;
-    pub type Client = subxt::OnlineClient<Config>;
+impl Chain {
+    fn endpoints(&self) -> &'static [&'static str] {
+        match self {
+            Chain::Kusama => &[
+                "wss://rpc.dotters.network/kusama",
+                "wss://rpc-kusama.luckyfriday.io",
+                "wss://kusama.api.onfinality.io/public-ws",
+                "wss://kusama.public.curie.radiumblock.co/ws",
+                "wss://ksm-rpc.stakeworld.io",
+            ],
+            Chain::Polkadot => &[
+                "wss://rpc.dotters.network/polkadot",
+                "wss://rpc-polkadot.luckyfriday.io",
+                "wss://polkadot.api.onfinality.io/public-ws",
+                "wss://polkadot.public.curie.radiumblock.co/ws",
+                "wss://dot-rpc.stakeworld.io",
+            ],
+            Chain::Westend => &[
+                "wss://westend-rpc.polkadot.io",
+                "wss://rpc.dotters.network/westend",
+                "wss://westend.api.onfinality.io/public-ws",
+            ],
+            Chain::KusamaAssetHub => &[
+                "wss://kusama-asset-hub-rpc.polkadot.io",
+                "wss://sys.ibp.network/statemine",
+                "wss://statemine.api.onfinality.io/public-ws",
+            ],
+            Chain::PolkadotAssetHub => &[
+                "wss://polkadot-asset-hub-rpc.polkadot.io",
+                "wss://sys.ibp.network/statemint",
+                "wss://statemint.api.onfinality.io/public-ws",
+            ],
+        }
+    }
+
+    /// The SS58 address format of the chain.
+    pub fn ss58_prefix(&self) -> u16 {
+        match self {
+            Chain::Kusama | Chain::KusamaAssetHub => 2,
+            Chain::Polkadot | Chain::PolkadotAssetHub => 0,
+            Chain::Westend => 42,
+        }
+    }
 
-    const ENDPOINTS: &[&str] = &[
-        "wss://rpc.dotters.network/kusama",
-        "wss://rpc-kusama.luckyfriday.io",
-        "wss://kusama.api.onfinality.io/public-ws",
-        "wss://kusama.public.curie.radiumblock.co/ws",
-        "wss://ksm-rpc.stakeworld.io",
-    ];
+    /// The symbol and the number of decimals of the native token.
+    pub fn token(&self) -> (&'static str, u32) {
+        match self {
+            Chain::Kusama | Chain::KusamaAssetHub => ("KSM", 12),
+            Chain::Polkadot | Chain::PolkadotAssetHub => ("DOT", 10),
+            Chain::Westend => ("WND", 12),
+        }
+    }
+
+    /// Formats the amount of the smallest units in the native token, e.g. `0.0015 KSM`.
+    pub fn format_balance(&self, amount: u128) -> String {
+        let (symbol, decimals) = self.token();
+        let unit = 10u128.pow(decimals);
+        let fraction = format!("{:0width$}", amount % unit, width = decimals as usize);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            format!("{} {}", amount / unit, symbol)
+        } else {
+            format!("{}.{} {}", amount / unit, fraction, symbol)
+        }
+    }
+
+    /// Parses an amount of the native token, e.g. `0.0015`, into the smallest units.
+    pub fn parse_balance(&self, amount: &str) -> anyhow::Result<u128> {
+        let (_, decimals) = self.token();
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let valid = |digits: &str| digits.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty() || !valid(whole) || !valid(fraction) {
+            anyhow::bail!("Invalid amount {}", amount);
+        }
+        if fraction.len() > decimals as usize {
+            anyhow::bail!("{} has more than {} decimals", amount, decimals);
+        }
+        // Both parts are padded to the same number of digits, so they could be parsed as one.
+        let digits = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+        digits
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Amount {} is too large", amount))
+    }
 
-    pub fn pick_endpoint(endpoint: Option<&str>) -> &str {
+    /// Whether the calls could be built with the statically generated metadata.
+    pub fn has_static_metadata(&self) -> bool {
+        matches!(self, Chain::Kusama)
+    }
+
+    pub fn pick_endpoint<'a>(&self, endpoint: Option<&'a str>) -> &'a str {
         use rand::Rng;
         match endpoint {
             Some(endpoint) => endpoint,
             None => {
+                let endpoints = self.endpoints();
                 let mut rng = rand::thread_rng();
-                let index = rng.gen_range(0..ENDPOINTS.len());
-                ENDPOINTS[index]
+                let index = rng.gen_range(0..endpoints.len());
+                endpoints[index]
             }
         }
     }
 
-    pub async fn new_client(endpoint: &str) -> anyhow::Result<Client> {
-        let client = Client::from_url(endpoint).await?;
-        Ok(client)
+    /// Formats the account as an SS58 address of this chain.
+    pub fn to_ss58(self, account: &AccountId32) -> String {
+        to_ss58(self.ss58_prefix(), account)
+    }
+}
+
+/// The generic substrate SS58 address format.
+pub const SUBSTRATE_SS58_PREFIX: u16 = 42;
+
+/// Formats the account as an SS58 address with the given prefix.
+pub fn to_ss58(prefix: u16, account: &AccountId32) -> String {
+    use blake2::{Blake2b512, Digest};
+
+    let mut data = match prefix {
+        0..=63 => vec![prefix as u8],
+        _ => vec![
+            ((prefix & 0b1111_1100) as u8 >> 2) | 0b0100_0000,
+            (prefix >> 8) as u8 | ((prefix & 0b0000_0011) as u8) << 6,
+        ],
+    };
+    data.extend_from_slice(&account.0);
+    let checksum = Blake2b512::new()
+        .chain_update(b"SS58PRE")
+        .chain_update(&data)
+        .finalize();
+    data.extend_from_slice(&checksum[..2]);
+    bs58::encode(data).into_string()
+}
+
+/// Connects to the endpoint, returning the client along with the legacy RPC methods over the same
+/// connection.
+pub async fn new_client(endpoint: &str) -> anyhow::Result<(Client, LegacyRpc)> {
+    let rpc_client = subxt::backend::rpc::RpcClient::from_url(endpoint).await?;
+    let client = Client::from_rpc_client(rpc_client.clone()).await?;
+    Ok((client, LegacyRpc::new(rpc_client)))
+}
+
+/// Checks whether the generated calls still match the live metadata of the chain.
+///
+/// A runtime upgrade could change the call indices or the signatures after the metadata was
+/// generated, in which case the calls must be built dynamically.
+pub fn validate_static(client: &Client) -> bool {
+    let dest = subxt::utils::MultiAddress::Id(AccountId32([0; 32]));
+    let xfer = kusama::tx().balances().transfer_all(dest, false);
+    let keep_alive = kusama::tx()
+        .balances()
+        .transfer_keep_alive(subxt::utils::MultiAddress::Id(AccountId32([0; 32])), 0);
+    let mint = kusama::tx().system().remark_with_event(vec![]);
+    client.tx().validate(&xfer).is_ok()
+        && client.tx().validate(&keep_alive).is_ok()
+        && client.tx().validate(&mint).is_ok()
+}
+
+/// The calls built against the metadata fetched at runtime.
+pub mod dynamic {
+    use subxt::{dynamic::Value, tx::DynamicPayload, utils::AccountId32};
+
+    pub fn transfer_all(dest: AccountId32, keep_alive: bool) -> DynamicPayload {
+        subxt::dynamic::tx(
+            "Balances",
+            "transfer_all",
+            vec![
+                Value::unnamed_variant("Id", [Value::from_bytes(dest.0)]),
+                Value::bool(keep_alive),
+            ],
+        )
+    }
+
+    pub fn transfer_keep_alive(dest: AccountId32, value: u128) -> DynamicPayload {
+        subxt::dynamic::tx(
+            "Balances",
+            "transfer_keep_alive",
+            vec![
+                Value::unnamed_variant("Id", [Value::from_bytes(dest.0)]),
+                Value::u128(value),
+            ],
+        )
+    }
+
+    pub fn remark_with_event(remark: Vec<u8>) -> DynamicPayload {
+        subxt::dynamic::tx(
+            "System",
+            "remark_with_event",
+            vec![Value::from_bytes(remark)],
+        )
     }
 }
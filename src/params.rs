@@ -0,0 +1,131 @@
+//! The tip and the mortality of the signed transactions.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use subxt::config::substrate::H256;
+use tokio::sync::Mutex;
+
+use crate::metadata::{self, Chain};
+
+/// How many of the recent blocks are looked at to pick a percentile tip.
+const TIP_SAMPLE_BLOCKS: usize = 5;
+
+/// The tip added to every transaction.
+#[derive(Clone, Copy, Debug)]
+pub enum Tip {
+    /// A fixed amount in the smallest units.
+    Fixed(u128),
+    /// The given percentile of the tips paid in the recent blocks.
+    Percentile(u8),
+}
+
+impl Tip {
+    /// Parses either an amount of the native token, e.g. `0.001`, or a percentile, e.g. `p90`.
+    pub fn parse(chain: Chain, tip: &str) -> Result<Self> {
+        match tip.strip_prefix('p') {
+            Some(percentile) => {
+                let percentile = percentile
+                    .parse::<u8>()
+                    .map_err(|_| anyhow!("Invalid tip percentile {}", tip))?;
+                if percentile > 100 {
+                    bail!("Invalid tip percentile {}", tip);
+                }
+                Ok(Tip::Percentile(percentile))
+            }
+            None => Ok(Tip::Fixed(chain.parse_balance(tip)?)),
+        }
+    }
+}
+
+/// The signing parameters of the transactions other than the nonce.
+#[derive(Clone)]
+pub struct Params {
+    tip: Tip,
+    /// For how many blocks the transactions are valid. Immortal if `None`.
+    mortality: Option<u64>,
+    /// The percentile tip along with the best block it was picked at.
+    cached_tip: Arc<Mutex<Option<(H256, u128)>>>,
+}
+
+impl Default for Params {
+    /// No tip and immortal, just like `Default::default()` of the extrinsic params.
+    fn default() -> Self {
+        Self::new(Tip::Fixed(0), None)
+    }
+}
+
+impl Params {
+    pub fn new(tip: Tip, mortality: Option<u64>) -> Self {
+        Self {
+            tip,
+            mortality,
+            cached_tip: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn is_mortal(&self) -> bool {
+        self.mortality.is_some()
+    }
+
+    /// Builds the parameters for the transactions signed as of the given best block.
+    pub async fn build(
+        &self,
+        client: &metadata::Client,
+        best: H256,
+    ) -> Result<metadata::OtherParams> {
+        let mut params = subxt::config::SubstrateExtrinsicParamsBuilder::<metadata::Config>::new()
+            .tip(self.tip(client, best).await?);
+        if let Some(mortality) = self.mortality {
+            let header = client
+                .backend()
+                .block_header(best)
+                .await?
+                .ok_or_else(|| anyhow!("best block {:?} not found", best))?;
+            params = params.mortal(&header, mortality);
+        }
+        Ok(params.build())
+    }
+
+    /// Returns the tip of the transactions signed as of the given best block.
+    pub async fn tip(&self, client: &metadata::Client, best: H256) -> Result<u128> {
+        let percentile = match self.tip {
+            Tip::Fixed(tip) => return Ok(tip),
+            Tip::Percentile(percentile) => percentile,
+        };
+        // The tips don't change within a block, so they are only sampled once per block.
+        let mut cached_tip = self.cached_tip.lock().await;
+        if let Some((hash, tip)) = *cached_tip {
+            if hash == best {
+                return Ok(tip);
+            }
+        }
+        let mut tips = recent_tips(client, best).await?;
+        tips.sort_unstable();
+        let tip = match tips.len() {
+            0 => 0,
+            len => tips[(len - 1) * percentile as usize / 100],
+        };
+        *cached_tip = Some((best, tip));
+        Ok(tip)
+    }
+}
+
+/// Returns the tips of the signed transactions in the recent blocks up to the given one.
+async fn recent_tips(client: &metadata::Client, mut hash: H256) -> Result<Vec<u128>> {
+    let mut tips = Vec::new();
+    for _ in 0..TIP_SAMPLE_BLOCKS {
+        let block = client.blocks().at(hash).await?;
+        for ext in block.extrinsics().await?.iter() {
+            let ext = ext?;
+            if let Some(tip) = ext.signed_extensions().and_then(|ext| ext.tip()) {
+                tips.push(tip);
+            }
+        }
+        if block.number() == 0 {
+            break;
+        }
+        hash = block.header().parent_hash;
+    }
+    Ok(tips)
+}
@@ -0,0 +1,206 @@
+//! Decrypting the JSON exports of polkadot-js, Talisman and the like.
+//!
+//! The export holds the PKCS8-encoded keypair encrypted with xsalsa20-poly1305 under a key derived
+//! from the password with scrypt.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use crypto_secretbox::{aead::Aead, KeyInit, XSalsa20Poly1305};
+use serde::Deserialize;
+use subxt::utils::AccountId32;
+use zeroize::Zeroizing;
+
+use crate::key::Keypair;
+
+const PKCS8_HEADER: &[u8] = &[48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: &[u8] = &[161, 35, 3, 33, 0];
+const SECRET_KEY_LEN: usize = 64;
+const PUBLIC_KEY_LEN: usize = 32;
+
+const SALT_LEN: usize = 32;
+const SCRYPT_PARAMS_LEN: usize = 12;
+const NONCE_LEN: usize = 24;
+
+#[derive(Deserialize)]
+struct Export {
+    encoded: String,
+    encoding: Encoding,
+    /// The SS58 address of the account, for any network.
+    address: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Encoding {
+    content: Vec<String>,
+    #[serde(rename = "type")]
+    ty: Vec<String>,
+}
+
+/// Decrypts the given JSON export into a keypair.
+pub fn decrypt(json: &str, password: &str) -> Result<Keypair> {
+    let export: Export = serde_json::from_str(json)?;
+    if export.encoding.ty != ["scrypt", "xsalsa20-poly1305"] {
+        bail!("Unsupported keystore encryption: {:?}", export.encoding.ty);
+    }
+    if export.encoding.content != ["pkcs8", "sr25519"] {
+        bail!(
+            "Unsupported keystore content: {:?}",
+            export.encoding.content
+        );
+    }
+
+    let encoded = base64::engine::general_purpose::STANDARD.decode(&export.encoded)?;
+    let pkcs8 = decrypt_encoded(&encoded, password)?;
+    let keypair = decode_pkcs8(&pkcs8)?;
+    if let Some(address) = &export.address {
+        let account_id = AccountId32::from_str(address)
+            .map_err(|e| anyhow!("Invalid address {}: {:?}", address, e))?;
+        if account_id != keypair.account_id() {
+            bail!("The keystore is not of {}", address);
+        }
+    }
+    Ok(keypair)
+}
+
+/// Decrypts the `encoded` part of an export, i.e. the salt, the scrypt parameters, the nonce and
+/// the ciphertext, in this order.
+pub fn decrypt_encoded(encoded: &[u8], password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if encoded.len() < SALT_LEN + SCRYPT_PARAMS_LEN + NONCE_LEN {
+        bail!("Keystore is too short");
+    }
+    let (salt, rest) = encoded.split_at(SALT_LEN);
+    let (params, rest) = rest.split_at(SCRYPT_PARAMS_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    // N, p and r as little-endian u32s, in this order.
+    let param = |i: usize| u32::from_le_bytes(params[i * 4..i * 4 + 4].try_into().unwrap());
+    let (n, p, r) = (param(0), param(1), param(2));
+    if !n.is_power_of_two() {
+        bail!("Invalid scrypt parameter N: {}", n);
+    }
+    let cipher = cipher(password, salt, n.trailing_zeros() as u8, p, r)?;
+    let plaintext = cipher
+        .decrypt(nonce.into(), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt the keystore, wrong password?"))?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Encrypts the given plaintext the same way polkadot-js does. The result could be decrypted with
+/// [`decrypt_encoded`].
+pub fn encrypt_encoded(plaintext: &[u8], password: &str) -> Result<Vec<u8>> {
+    use rand::RngCore;
+
+    // The defaults of polkadot-js.
+    let (log_n, p, r) = (15u8, 1u32, 8u32);
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let cipher = cipher(password, &salt, log_n, p, r)?;
+    let ciphertext = cipher
+        .encrypt(nonce.as_slice().into(), plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt the keystore"))?;
+
+    let mut encoded =
+        Vec::with_capacity(SALT_LEN + SCRYPT_PARAMS_LEN + NONCE_LEN + ciphertext.len());
+    encoded.extend_from_slice(&salt);
+    encoded.extend_from_slice(&(1u32 << log_n).to_le_bytes());
+    encoded.extend_from_slice(&p.to_le_bytes());
+    encoded.extend_from_slice(&r.to_le_bytes());
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&ciphertext);
+    Ok(encoded)
+}
+
+fn cipher(password: &str, salt: &[u8], log_n: u8, p: u32, r: u32) -> Result<XSalsa20Poly1305> {
+    let params = scrypt::Params::new(log_n, r, p, 32)
+        .map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key[..])
+        .map_err(|e| anyhow!("scrypt failed: {}", e))?;
+    let cipher =
+        XSalsa20Poly1305::new_from_slice(&key[..]).map_err(|_| anyhow!("Invalid key length"))?;
+    Ok(cipher)
+}
+
+fn decode_pkcs8(pkcs8: &[u8]) -> Result<Keypair> {
+    let secret = pkcs8
+        .strip_prefix(PKCS8_HEADER)
+        .filter(|rest| rest.len() >= SECRET_KEY_LEN)
+        .ok_or_else(|| anyhow!("Invalid PKCS8 header"))?;
+    let (secret, rest) = secret.split_at(SECRET_KEY_LEN);
+    let public = rest
+        .strip_prefix(PKCS8_DIVIDER)
+        .filter(|public| public.len() == PUBLIC_KEY_LEN)
+        .ok_or_else(|| anyhow!("Invalid PKCS8 divider"))?;
+
+    // polkadot-js stores the secret key in the ed25519-compatible form.
+    let secret = schnorrkel::SecretKey::from_ed25519_bytes(secret)
+        .map_err(|e| anyhow!("Invalid secret key: {}", e))?;
+    let keypair = secret.to_keypair();
+    if keypair.public.to_bytes() != public {
+        bail!("Public key doesn't match the secret key");
+    }
+    Ok(Keypair::Expanded(keypair))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+    const ALICE: &str = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY";
+    const BOB: &str = "5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty";
+
+    /// The export of `//Alice` in the format of polkadot-js, with the default scrypt parameters.
+    fn export(address: &str) -> String {
+        format!(
+            r#"{{
+                "encoded": "u6wKINWJVRV+C32Gdb3ZfZXIXOsuaOaXXwm0hvs7ThIAgAAAAQAAAAgAAAAE4q6cawqwvaPGCNOsUVlI/j5vf7NPzTW+xRJtZ7Rvf+lNJ/uNkFHwYQyENV3glss9gEm+8ORU9Idgu0ILi0MIdsALBcLEFN3a5YEIjH7foktNQdo3hUdVO0JklxREoq/VDi7YtqkWqqNYAEQ5dO495CXyPni3Pz+0bXXgdlQSy8zNX1KboVpdVcVBotOe0f50zPqXoOEBOolGHlA9",
+                "encoding": {{
+                    "content": ["pkcs8", "sr25519"],
+                    "type": ["scrypt", "xsalsa20-poly1305"],
+                    "version": "3"
+                }},
+                "address": "{}",
+                "meta": {{ "genesisHash": "", "name": "alice", "whenCreated": 1700000000000 }}
+            }}"#,
+            address
+        )
+    }
+
+    #[test]
+    fn decrypts() {
+        let keypair = decrypt(&export(ALICE), PASSWORD).unwrap();
+        assert_eq!(keypair.account_id(), AccountId32::from_str(ALICE).unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        assert!(decrypt(&export(ALICE), "wrong").is_err());
+    }
+
+    #[test]
+    fn rejects_other_address() {
+        assert!(decrypt(&export(BOB), PASSWORD).is_err());
+    }
+
+    #[test]
+    fn encoded_round_trip() {
+        let encoded = encrypt_encoded(b"//Alice", PASSWORD).unwrap();
+        // N = 2^15, p = 1 and r = 8 between the salt and the nonce, like polkadot-js.
+        assert_eq!(
+            encoded[SALT_LEN..SALT_LEN + SCRYPT_PARAMS_LEN],
+            [0, 128, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0]
+        );
+        assert_eq!(
+            decrypt_encoded(&encoded, PASSWORD).unwrap().as_slice(),
+            b"//Alice"
+        );
+        assert!(decrypt_encoded(&encoded, "wrong").is_err());
+        assert!(decrypt_encoded(&encoded[..SALT_LEN], PASSWORD).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Reporting how much the inscribing cost.
+//!
+//! The fees are recorded by the [`watcher`](crate::watcher) as the transactions get finalized, so
+//! the report only needs the database.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    db::{Costs, Database},
+    Cli,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    /// The period to break the costs down by.
+    #[clap(long, value_enum, default_value = "day")]
+    period: Period,
+    /// Print the report as JSON. The amounts are in the smallest units of the native token.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Period {
+    Hour,
+    Day,
+    Month,
+}
+
+impl Period {
+    /// The `strftime` format the block times are grouped by.
+    fn format(&self) -> &'static str {
+        match self {
+            Period::Hour => "%Y-%m-%d %H:00",
+            Period::Day => "%Y-%m-%d",
+            Period::Month => "%Y-%m",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    total: Costs,
+    /// The average cost of a successful mint, including the transfer that funded it.
+    per_mint: Option<u128>,
+    periods: Vec<Costs>,
+}
+
+pub async fn run(cli: &Cli, args: &ReportArgs) -> Result<()> {
+    let chain = cli.chain()?;
+    let db = Database::new().await?;
+    let periods = db.costs(args.period.format()).await?;
+    let mut total = Costs {
+        period: "total".to_string(),
+        txns: 0,
+        unknown_fees: 0,
+        mints: 0,
+        fees: 0,
+    };
+    for costs in &periods {
+        total.txns += costs.txns;
+        total.unknown_fees += costs.unknown_fees;
+        total.mints += costs.mints;
+        total.fees += costs.fees;
+    }
+    let per_mint = (total.mints > 0).then(|| total.fees / total.mints as u128);
+    let report = Report {
+        total,
+        per_mint,
+        periods,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    println!(
+        "spent {} on {} finalized transactions",
+        chain.format_balance(report.total.fees),
+        report.total.txns
+    );
+    if report.total.unknown_fees > 0 {
+        println!(
+            "the fees of {} of them were not recorded",
+            report.total.unknown_fees
+        );
+    }
+    match report.per_mint {
+        Some(per_mint) => println!(
+            "{} successful mints, {} per mint",
+            report.total.mints,
+            chain.format_balance(per_mint)
+        ),
+        None => println!("no successful mints"),
+    }
+    println!();
+    println!(
+        "{:<16} {:>8} {:>8} {:>24}",
+        "period", "txns", "mints", "fees"
+    );
+    for costs in &report.periods {
+        println!(
+            "{:<16} {:>8} {:>8} {:>24}",
+            costs.period,
+            costs.txns,
+            costs.mints,
+            chain.format_balance(costs.fees)
+        );
+    }
+    Ok(())
+}
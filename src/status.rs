@@ -0,0 +1,90 @@
+//! Summarizing the progress of the inscribing.
+//!
+//! Unlike the other commands, the status doesn't need the root key: everything it reports on is
+//! either in the database or could be queried by the recorded addresses.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use subxt::utils::AccountId32;
+
+use crate::{db::Database, Cli, Rpc};
+
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    /// Print the status as JSON. The amounts are in the smallest units of the native token.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Status {
+    next_index: u32,
+    /// The number of transactions in every status.
+    txns: BTreeMap<String, i64>,
+    /// The number of mints that made it on chain successfully.
+    mints: u64,
+    /// The fees paid by the finalized transactions. See the `report` command for the details.
+    fees: u128,
+    /// The account holding the funds. `None` if the funds are still with the root account.
+    holder: Option<Holder>,
+}
+
+#[derive(Serialize)]
+struct Holder {
+    index: u32,
+    address: String,
+    free: u128,
+}
+
+pub async fn run(cli: &Cli, args: &StatusArgs) -> Result<()> {
+    let chain = cli.chain()?;
+    let db = Database::new().await?;
+    let endpoint = chain.pick_endpoint(cli.endpoint.as_deref());
+    let rpc = Rpc::new(endpoint, chain, cli.dynamic_metadata).await?;
+    db.check_genesis_hash(rpc.client.genesis_hash()).await?;
+
+    let holder = match db.get_holder().await? {
+        Some(account) => {
+            let account_id = AccountId32::from_str(&account.address)
+                .map_err(|e| anyhow!("Invalid address {}: {:?}", account.address, e))?;
+            let info = rpc.get_account_info(&account_id).await?;
+            Some(Holder {
+                index: account.index,
+                address: account.address,
+                free: info.data.free,
+            })
+        }
+        None => None,
+    };
+    let status = Status {
+        next_index: db.get_next_index().await?,
+        txns: db.txn_counts().await?.into_iter().collect(),
+        mints: db.mint_count().await?,
+        fees: db.total_fees().await?,
+        holder,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    println!("next index: {}", status.next_index);
+    println!("transactions:");
+    for (status, count) in &status.txns {
+        println!("  {}: {}", status, count);
+    }
+    println!("successful mints: {}", status.mints);
+    println!("fees paid: {}", chain.format_balance(status.fees));
+    match &status.holder {
+        Some(holder) => println!(
+            "funds are held by {} ({}): {}",
+            holder.index,
+            holder.address,
+            chain.format_balance(holder.free)
+        ),
+        None => println!("funds are held by the root account"),
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! Stopping once the max supply of the minted tick is reached.
+//!
+//! The mints past the max supply are worthless, yet the fees are paid all the same. The supply is
+//! looked up either in the local index, see [`indexer`](crate::indexer), or at an HTTP endpoint of
+//! an indexer.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+use crate::{
+    db::Database,
+    indexer::Indexer,
+    inscription::{token, InscriptionProtocol},
+    metadata::Chain,
+};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum Source {
+    /// The local index, caught up with the chain on every check.
+    Local(Box<Indexer>),
+    /// The URL of an indexer returning `{"minted": ..., "max": ...}` for the tick. The amounts are
+    /// either numbers or decimal strings.
+    Endpoint { http: reqwest::Client, url: String },
+}
+
+pub struct SupplyCheck {
+    tick: String,
+    source: Source,
+}
+
+impl SupplyCheck {
+    /// Sets up the check of the tick minted by the inscription. `source` is either `local` or the
+    /// URL of an indexer, where `{tick}` is replaced with the tick.
+    pub async fn new(
+        source: &str,
+        inscription: &dyn InscriptionProtocol,
+        chain: Chain,
+        endpoint: &str,
+        db: &Database,
+    ) -> Result<Self> {
+        let Some(tick) = inscription.minted_tick() else {
+            bail!(
+                "The {} inscription doesn't mint a supply",
+                inscription.name()
+            );
+        };
+        let tick = tick.to_lowercase();
+        let source = if source == "local" {
+            if inscription.name() != token::KSM20 {
+                bail!("The local index only covers ksm-20");
+            }
+            Source::Local(Box::new(Indexer::connect(endpoint, chain, db).await?))
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            Source::Endpoint {
+                http: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+                url: source.replace("{tick}", &tick),
+            }
+        } else {
+            bail!(
+                "Expected `local` or an HTTP URL for the supply, got {}",
+                source
+            );
+        };
+        Ok(SupplyCheck { tick, source })
+    }
+
+    /// Returns whether the whole supply of the tick is minted.
+    pub async fn is_exhausted(&mut self, db: &Database) -> Result<bool> {
+        let (minted, max) = self.supply(db).await?;
+        println!("{}: minted {} of {}", self.tick, minted, max);
+        Ok(minted >= max)
+    }
+
+    /// Returns the minted and the max supply of the tick.
+    async fn supply(&mut self, db: &Database) -> Result<(u128, u128)> {
+        match &mut self.source {
+            Source::Local(indexer) => {
+                let finalized = indexer.catch_up(db).await?;
+                let deployed = indexer
+                    .tick(&self.tick)
+                    .ok_or_else(|| anyhow!("{} is not deployed as of #{}", self.tick, finalized))?;
+                Ok((deployed.minted, deployed.max))
+            }
+            Source::Endpoint { http, url } => {
+                let supply = http
+                    .get(url.as_str())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Value>()
+                    .await?;
+                Ok((amount(&supply, "minted")?, amount(&supply, "max")?))
+            }
+        }
+    }
+}
+
+fn amount(supply: &Value, field: &str) -> Result<u128> {
+    let amount = match &supply[field] {
+        Value::Number(number) => number.as_u64().map(u128::from),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    };
+    amount.ok_or_else(|| anyhow!("Invalid {} in the supply: {}", field, supply))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::inscription::token::Token;
+
+    /// Answers a single request for `/sama` with the given status and body, anything else with a
+    /// 404. Returns the URL template of the supply.
+    fn serve(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/{{tick}}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+            let status = if request.starts_with(b"GET /sama ") {
+                status
+            } else {
+                "404 Not Found"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        url
+    }
+
+    async fn is_exhausted(status: &'static str, body: &'static str) -> Result<bool> {
+        let db = Database::open("sqlite::memory:").await?;
+        let mint = br#"{"p":"ksm-20","op":"mint","tick":"SAMA","amt":"1000"}"#;
+        let inscription = Token::parse(token::KSM20, mint)?;
+        let url = serve(status, body);
+        let mut check = SupplyCheck::new(&url, &inscription, Chain::Kusama, "", &db).await?;
+        check.is_exhausted(&db).await
+    }
+
+    #[tokio::test]
+    async fn numbers() {
+        let body = r#"{"minted": 1000, "max": 21000000}"#;
+        assert!(!is_exhausted("200 OK", body).await.unwrap());
+        let body = r#"{"minted": 21000000, "max": 21000000}"#;
+        assert!(is_exhausted("200 OK", body).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn strings() {
+        // Past what fits into a JSON number without losing precision.
+        let body = r#"{"minted": "340282366920938463463374607431768211455", "max": "21000000"}"#;
+        assert!(is_exhausted("200 OK", body).await.unwrap());
+        let body = r#"{"minted": "0", "max": "21000000"}"#;
+        assert!(!is_exhausted("200 OK", body).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        let body = r#"{"minted": 1000, "max": 21000000}"#;
+        assert!(is_exhausted("500 Internal Server Error", body)
+            .await
+            .is_err());
+        assert!(is_exhausted("200 OK", r#"{"minted": 1000}"#).await.is_err());
+        assert!(is_exhausted("200 OK", r#"{"minted": -1, "max": 1}"#)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Consolidating the funds left in the derived accounts.
+//!
+//! Normally, the whole balance sits in the last derived account. However, the earlier accounts may
+//! hold something as well, e.g. if a run was interrupted midway.
+
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use subxt::utils::AccountId32;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{db::TxnKind, sign_transfer_all, AccountState, Session, SubmitError};
+
+#[derive(clap::Args, Debug)]
+pub struct SweepArgs {
+    /// The SS58 address to move the funds to. Defaults to the root account.
+    #[clap(long, value_name = "ADDRESS")]
+    dest: Option<String>,
+    /// How many sweeps to submit at once.
+    #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+}
+
+pub const DEFAULT_CONCURRENCY: usize = 16;
+
+pub async fn run(session: &Session, args: &SweepArgs) -> Result<()> {
+    let dest = match &args.dest {
+        Some(dest) => AccountId32::from_str(dest)
+            .map_err(|e| anyhow!("Invalid destination {}: {:?}", dest, e))?,
+        None => session.accounts.root().account_id(),
+    };
+    sweep(session, dest, args.concurrency).await
+}
+
+/// Transfers everything from the derived accounts `0..next_index` to the destination.
+///
+/// The balances below the existential deposit are left alone: they would be eaten up by the fees
+/// and couldn't endow an empty destination anyway.
+pub async fn sweep(session: &Session, dest: AccountId32, concurrency: usize) -> Result<()> {
+    let Session {
+        chain,
+        rpc,
+        db,
+        accounts,
+    } = session;
+    println!("sweeping to {}", chain.to_ss58(&dest));
+
+    let existential_deposit = rpc.existential_deposit()?;
+    let next_index = db.get_next_index().await?;
+    let inflight = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut sweeps = JoinSet::new();
+    let mut dust = 0;
+    for index in 0..next_index {
+        let keypair = accounts.derive(index);
+        let account_id = keypair.account_id();
+        let info = rpc.get_account_info(&account_id).await?;
+        let free = info.data.free;
+        if free == 0 {
+            continue;
+        }
+        let address = chain.to_ss58(&account_id);
+        if free < existential_deposit {
+            println!("{} ({}) holds dust: {}", index, address, free);
+            dust += free;
+            continue;
+        }
+
+        let sender = AccountState {
+            index: Some(index),
+            keypair,
+            nonce: info.nonce as u64,
+        };
+        let txn = sign_transfer_all(rpc, &sender, dest.clone(), TxnKind::Sweep).await?;
+        db.insert(txn.clone()).await?;
+        println!("sweeping {} from {} ({})", free, index, address);
+
+        let permit = inflight.clone().acquire_owned().await?;
+        let (rpc, db) = (rpc.clone(), db.clone());
+        sweeps.spawn(async move {
+            let _permit = permit;
+            match rpc.submit(&db, txn).await {
+                Ok(_) => Ok(free),
+                Err(SubmitError::Other(err)) => Err(err),
+                Err(err) => {
+                    println!("sweeping {} failed: {}", address, err);
+                    Ok(0)
+                }
+            }
+        });
+    }
+
+    let mut swept = 0;
+    while let Some(result) = sweeps.join_next().await {
+        swept += result??;
+    }
+    println!("swept {} (before fees), left {} in dust", swept, dust);
+    Ok(())
+}
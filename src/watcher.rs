@@ -0,0 +1,199 @@
+//! Follows the finalized blocks and confirms the transactions that were included in them. The best
+//! blocks are followed as well, so that the submissions learn of the inclusion of their
+//! transactions even if the status streams of the node end early.
+//!
+//! The progress is persisted in `last_block`, so the blocks finalized while we were not running
+//! are caught up with on the next start, up to `--max-catch-up` blocks back.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, bail, Result};
+use subxt::{
+    blocks::Block,
+    config::{substrate::H256, Hasher},
+};
+use tokio::sync::oneshot;
+
+use crate::{db::Database, metadata, metadata::kusama::transaction_payment, Rpc};
+
+type FinalizedBlock = Block<metadata::Config, metadata::Client>;
+
+/// How many best blocks are walked back at most when some were skipped by the subscription.
+const MAX_MISSED_BEST_BLOCKS: usize = 16;
+
+/// The transactions waiting to be seen in a best block, along with whom to tell the block hash.
+///
+/// Once the watcher stops, nothing is waited for anymore and the waiting submissions fail.
+#[derive(Default)]
+pub struct Inclusions {
+    /// `None` once the watcher stopped.
+    waiting: Mutex<Option<HashMap<H256, oneshot::Sender<H256>>>>,
+}
+
+impl Inclusions {
+    /// Starts waiting for the transaction with the given hash to be included in a best block.
+    pub fn wait(&self, txn_hash: H256) -> Result<oneshot::Receiver<H256>> {
+        let (tx, rx) = oneshot::channel();
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting
+            .as_mut()
+            .ok_or_else(|| anyhow!("The watcher stopped, the inclusion could not be tracked"))?
+            .insert(txn_hash, tx);
+        Ok(rx)
+    }
+
+    /// Stops waiting for the transaction, whether it was seen or not.
+    pub fn forget(&self, txn_hash: H256) {
+        if let Some(waiting) = self.waiting.lock().unwrap().as_mut() {
+            waiting.remove(&txn_hash);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiting
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(HashMap::is_empty)
+    }
+
+    fn included(&self, txn_hash: H256, block_hash: H256) {
+        if let Some(waiting) = self.waiting.lock().unwrap().as_mut() {
+            if let Some(tx) = waiting.remove(&txn_hash) {
+                let _ = tx.send(block_hash);
+            }
+        }
+    }
+
+    /// Fails the waiting submissions and the ones to come.
+    fn close(&self) {
+        self.waiting.lock().unwrap().take();
+    }
+}
+
+pub async fn run(rpc: Rpc, db: Database, max_catch_up: u32) -> Result<()> {
+    // The submissions only need the best blocks, so they go on even if the finalized ones cannot
+    // be followed.
+    let finalized = async {
+        if let Err(err) = follow_finalized(&rpc, &db, max_catch_up).await {
+            println!("finalized block watcher failed: {:#}", err);
+        }
+    };
+    let best = follow_best(&rpc);
+    tokio::pin!(best);
+    let result = tokio::select! {
+        result = &mut best => result,
+        () = finalized => best.await,
+    };
+    rpc.inclusions.close();
+    result
+}
+
+async fn follow_finalized(rpc: &Rpc, db: &Database, max_catch_up: u32) -> Result<()> {
+    let mut blocks = rpc.client.blocks().subscribe_finalized().await?;
+    let mut last_block = db.get_last_block().await?;
+    while let Some(block) = blocks.next().await {
+        let block = block?;
+        if let Some(prev) = last_block {
+            // Some blocks were missed, e.g. while we were not running.
+            if let Err(err) = catch_up(rpc, db, prev + 1, block.number(), max_catch_up).await {
+                // Moving on would leave a gap. The blocks caught up with so far are kept, the rest
+                // are tried again with the next finalized block.
+                println!("catching up with the finalized blocks failed: {:#}", err);
+                last_block = db.get_last_block().await?;
+                continue;
+            }
+        }
+        process(db, &block).await?;
+        last_block = Some(block.number());
+    }
+    bail!("finalized block subscription ended")
+}
+
+/// Processes the finalized blocks from `from` up to `to`, exclusive, one by one. Only the last
+/// `max_catch_up` of them are processed, since the nodes prune the state of the older ones.
+async fn catch_up(rpc: &Rpc, db: &Database, from: u32, to: u32, max_catch_up: u32) -> Result<()> {
+    let from = if to.saturating_sub(from) > max_catch_up {
+        let skip_to = to - max_catch_up;
+        println!(
+            "skipping the finalized blocks #{}..#{}, the fees paid in them are not recorded",
+            from,
+            skip_to - 1
+        );
+        skip_to
+    } else {
+        from
+    };
+    for number in from..to {
+        // The finalized blocks are canonical, so they can be looked up by number.
+        let hash = rpc
+            .legacy
+            .chain_get_block_hash(Some(number.into()))
+            .await?
+            .ok_or_else(|| anyhow!("Finalized block #{} not found", number))?;
+        process(db, &rpc.client.blocks().at(hash).await?).await?;
+    }
+    Ok(())
+}
+
+/// Tells the waiting submissions about the best blocks their transactions made it into.
+async fn follow_best(rpc: &Rpc) -> Result<()> {
+    let mut blocks = rpc.client.blocks().subscribe_best().await?;
+    let mut last_block: Option<u32> = None;
+    while let Some(block) = blocks.next().await {
+        let block = block?;
+        let mut new = vec![block];
+        // The subscription could skip blocks, so walk back a bit to the last one seen.
+        if let Some(last_block) = last_block {
+            while new.len() < MAX_MISSED_BEST_BLOCKS && new[new.len() - 1].number() > last_block + 1
+            {
+                let parent_hash = new[new.len() - 1].header().parent_hash;
+                new.push(rpc.client.blocks().at(parent_hash).await?);
+            }
+        }
+        last_block = Some(new[0].number());
+        if rpc.inclusions.is_empty() {
+            continue;
+        }
+        for block in new.iter().rev() {
+            for ext in block.extrinsics().await?.iter() {
+                let ext = ext?;
+                let hash = <metadata::Config as subxt::Config>::Hasher::hash(ext.bytes());
+                rpc.inclusions.included(hash, block.hash());
+            }
+        }
+    }
+    bail!("best block subscription ended")
+}
+
+/// Looks for our transactions in the given finalized block and records the fees they paid.
+async fn process(db: &Database, block: &FinalizedBlock) -> Result<()> {
+    let unfinalized = db.unfinalized_txn_hashes().await?;
+    let mut found = Vec::new();
+    if !unfinalized.is_empty() {
+        let extrinsics = block.extrinsics().await?;
+        for ext in extrinsics.iter() {
+            let ext = ext?;
+            let hash = <metadata::Config as subxt::Config>::Hasher::hash(ext.bytes());
+            if unfinalized.contains(&hash) {
+                println!("txn {:?} finalized in #{}", hash, block.number());
+                let fee = ext
+                    .events()
+                    .await?
+                    .find_first::<transaction_payment::events::TransactionFeePaid>()?
+                    .map(|paid| paid.actual_fee);
+                found.push((hash, ext.index(), fee));
+            }
+        }
+    }
+    let time = if found.is_empty() {
+        None
+    } else {
+        // The layout is the same on all the supported chains.
+        let now = metadata::kusama::storage().timestamp().now().unvalidated();
+        block.storage().fetch(&now).await?
+    };
+    db.record_finalized_block(block.number(), block.hash(), time, &found)
+        .await?;
+    Ok(())
+}